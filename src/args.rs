@@ -1,5 +1,5 @@
 use std::path::PathBuf;
-use clap::{Parser, Subcommand, Args};
+use clap::{Parser, Subcommand, Args, ValueEnum};
 
 #[derive(Debug, Parser)]
 pub struct ImgcryptArgs {
@@ -19,7 +19,109 @@ pub enum Command {
     Remove(RemoveArgs),
 
     /// Print the contents of the PNG file
-    Print(PrintArgs)
+    Print(PrintArgs),
+
+    /// Generate a key pair and write the secret and public keys to files
+    Keygen(KeygenArgs),
+
+    /// Verify the signatures embedded in the PNG file
+    Verify(VerifyArgs),
+
+    /// Check the chunk structure of the PNG file against the PNG specification
+    Lint(LintArgs),
+
+    /// Read & edit textual metadata stored in tEXt, zTXt & iTXt chunks
+    Meta(MetaArgs),
+
+    /// Report the largest message or file each embedding method can hide in the PNG file
+    Capacity(CapacityArgs),
+
+    /// Analyse the pixels & structure of the PNG file for signs of hidden data
+    Detect(DetectArgs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+    /// Store the message in a separate chunk
+    Chunk,
+
+    /// Store the message in the least significant bits of the image pixels
+    Lsb,
+
+    /// Store the message in indexed-colour pixels by choosing between near-identical palette colours
+    Palette,
+
+    /// Store the message in the least significant bits of the alpha channel only
+    Alpha
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compress {
+    /// Compress with whichever algorithm gives the smallest output, only if it is smaller than the message
+    Auto,
+
+    /// Store the message uncompressed
+    None,
+
+    /// Compress with deflate
+    Deflate,
+
+    /// Compress with Zstandard
+    Zstd
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Position {
+    /// Follow the PNG chunk ordering rules, placing unknown chunks just before IEND
+    Auto,
+
+    /// Before the first IDAT chunk
+    BeforeIdat,
+
+    /// After the last IDAT chunk
+    AfterIdat
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Carrier {
+    /// A chunk of the given type holding nothing but the message
+    Chunk,
+
+    /// A standard text metadata entry written by cameras & image editors
+    Text,
+
+    /// Trailing data after the IEND chunk, which image viewers ignore
+    Trailer
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Camouflage {
+    /// Store the message as it is
+    None,
+
+    /// Store the message in a ZIP archive that unzip tools can open
+    Zip
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Interlace {
+    /// Keep the interlace method of the input image
+    Preserve,
+
+    /// Store the image data row by row
+    None,
+
+    /// Store the image data in seven Adam7 passes
+    Adam7
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Disguise {
+    /// XMP packet in an iTXt chunk, with the message posing as its thumbnail
+    Xmp,
+
+    /// Comment in a tEXt chunk
+    Comment
 }
 
 #[derive(Debug, Args)]
@@ -29,13 +131,89 @@ pub struct EncodeArgs {
     #[arg(short, long)]
     pub file_path: PathBuf,
 
-    /// Chunk type
+    /// Embedding method
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+
+    /// Chunk type, required for the chunk method
     #[arg(short, long)]
-    pub chunk_type: String,
+    pub chunk_type: Option<String>,
+
+    /// What holds the message for the chunk method
+    #[arg(long, value_enum, default_value_t = Carrier::Chunk)]
+    pub carrier: Carrier,
+
+    /// Text entry the message is disguised as for the text carrier
+    #[arg(long, value_enum, default_value_t = Disguise::Xmp)]
+    pub disguise_as: Disguise,
+
+    /// What the message looks like for the trailer carrier
+    #[arg(long, value_enum, default_value_t = Camouflage::None)]
+    pub camouflage: Camouflage,
+
+    /// Where to insert the message chunk for the chunk method
+    #[arg(long, value_enum, default_value_t = Position::Auto)]
+    pub position: Position,
+
+    /// Split the message into fragments stored in chunks of at most this many data bytes, for the chunk method
+    #[arg(long)]
+    pub max_chunk_size: Option<usize>,
+
+    /// Spread the fragments between the existing chunks instead of storing them together
+    #[arg(long, requires = "max_chunk_size")]
+    pub interleave: bool,
+
+    /// Channels carrying the message for the lsb method, any of the letters r, g, b & a
+    #[arg(long, default_value = "rgb")]
+    pub channels: String,
+
+    /// Number of low bits used in each channel for the lsb & alpha methods
+    #[arg(long, default_value_t = 1)]
+    pub bits: u8,
+
+    /// Only use pixels that are already partly transparent for the lsb & alpha methods, leaving opaque pixels untouched
+    #[arg(long)]
+    pub translucent_only: bool,
+
+    /// Hide this many bits in every 2^k - 1 samples with Hamming matrix encoding for the lsb & alpha methods, changing at most one, 2 to 8
+    #[arg(long)]
+    pub matrix: Option<u8>,
+
+    /// Interlace method of the output image, converting the image data if it differs from the input
+    #[arg(long, value_enum, default_value_t = Interlace::Preserve)]
+    pub interlace: Interlace,
+
+    /// Add Reed-Solomon parity of about this percent of the message size so corrupted bytes can be corrected, 1 to 200
+    #[arg(long)]
+    pub ecc: Option<u8>,
 
     /// Secret message to be hidden in PNG
+    #[arg(short, long, required_unless_present = "input_file", conflicts_with = "input_file")]
+    pub message: Option<String>,
+
+    /// Path to file to be hidden in PNG instead of a message, - reads from stdin
+    #[arg(long)]
+    pub input_file: Option<PathBuf>,
+
+    /// Compression applied to the message before it is encrypted & embedded
+    #[arg(long, value_enum, default_value_t = Compress::Auto)]
+    pub compress: Compress,
+
+    /// Optional password used to encrypt the message
+    #[arg(short, long, conflicts_with = "recipients")]
+    pub password: Option<String>,
+
+    /// Public key or path to recipient file to encrypt the message to, can be repeated
+    #[arg(short, long = "recipient")]
+    pub recipients: Vec<String>,
+
+    /// Optional path to Ed25519 signing key used to sign the message chunk
     #[arg(short, long)]
-    pub message: String,
+    pub sign_key: Option<PathBuf>,
+
+    /// Also sign all critical chunks of the PNG
+    #[arg(long, requires = "sign_key")]
+    pub sign_critical: bool,
 
     /// Optional path to output PNG file
     #[arg(short, long, default_value = "output.png")]
@@ -49,9 +227,49 @@ pub struct DecodeArgs {
     #[arg(short, long)]
     pub file_path: PathBuf,
 
-    /// Chunk type
+    /// Embedding method
+    #[arg(long, value_enum, default_value_t = Method::Chunk)]
+    pub method: Method,
+
+    /// Chunk type, required for the chunk method
     #[arg(short, long)]
-    pub chunk_type: String
+    pub chunk_type: Option<String>,
+
+    /// What holds the message for the chunk method, the text carrier searches Comment & XMP entries & the trailer carrier the data after IEND
+    #[arg(long, value_enum, default_value_t = Carrier::Chunk)]
+    pub carrier: Carrier,
+
+    /// Channels carrying the message for the lsb method, any of the letters r, g, b & a
+    #[arg(long, default_value = "rgb")]
+    pub channels: String,
+
+    /// Number of low bits used in each channel for the lsb & alpha methods
+    #[arg(long, default_value_t = 1)]
+    pub bits: u8,
+
+    /// Only read pixels that are partly transparent for the lsb & alpha methods, as given to encode
+    #[arg(long)]
+    pub translucent_only: bool,
+
+    /// Matrix encoding bits the message was encoded with for the lsb & alpha methods
+    #[arg(long)]
+    pub matrix: Option<u8>,
+
+    /// Password used to decrypt an encrypted message
+    #[arg(short, long)]
+    pub password: Option<String>,
+
+    /// Path to identity file used to decrypt a message encrypted to recipients
+    #[arg(short, long)]
+    pub identity: Option<PathBuf>,
+
+    /// Path to write the hidden data to instead of printing it, - writes the raw bytes to stdout
+    #[arg(short, long = "output-file")]
+    pub output_file_path: Option<PathBuf>,
+
+    /// Report every non-standard chunk that may hold hidden data instead of decoding a single chunk type
+    #[arg(long, conflicts_with_all = ["chunk_type", "output_file_path"])]
+    pub scan: bool
 }
 
 #[derive(Debug, Args)]
@@ -72,4 +290,188 @@ pub struct PrintArgs {
     /// Path to PNG file
     #[arg(short, long)]
     pub file_path: PathBuf
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+
+    /// Path to output secret key file, the public key is written next to it with a .pub extension
+    #[arg(short, long, default_value = "identity.key")]
+    pub output_file_path: PathBuf,
+
+    /// Generate an Ed25519 signing key instead of an X25519 identity
+    #[arg(short, long)]
+    pub signing: bool,
+
+    /// Overwrite the secret key file if it already exists
+    #[arg(long)]
+    pub force: bool
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Optional public key or path to public key file the signatures must be made with
+    #[arg(short, long)]
+    pub signer: Option<String>
+}
+
+#[derive(Debug, Args)]
+pub struct LintArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Fail on warnings as well as errors
+    #[arg(long)]
+    pub strict: bool
+}
+
+#[derive(Debug, Args)]
+pub struct MetaArgs {
+    #[clap(subcommand)]
+    pub action: MetaAction
+}
+
+#[derive(Debug, Subcommand)]
+pub enum MetaAction {
+    /// List every text entry
+    List(MetaListArgs),
+
+    /// Print the text stored under a keyword
+    Get(MetaGetArgs),
+
+    /// Store text under a keyword, replacing any existing entries with that keyword
+    Set(MetaSetArgs),
+
+    /// Remove every entry with a keyword
+    Delete(MetaDeleteArgs)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TextKind {
+    /// tEXt for short Latin-1 text, zTXt for long Latin-1 text, iTXt otherwise
+    Auto,
+
+    /// Uncompressed Latin-1 text
+    Text,
+
+    /// Compressed Latin-1 text
+    Ztxt,
+
+    /// UTF-8 text with optional language tag & translated keyword
+    Itxt
+}
+
+#[derive(Debug, Args)]
+pub struct MetaListArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf
+}
+
+#[derive(Debug, Args)]
+pub struct MetaGetArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Keyword, e.g. Author, Description or Comment
+    #[arg(short, long)]
+    pub keyword: String
+}
+
+#[derive(Debug, Args)]
+pub struct MetaSetArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Keyword, e.g. Author, Description or Comment
+    #[arg(short, long)]
+    pub keyword: String,
+
+    /// Text to store
+    #[arg(short, long)]
+    pub text: String,
+
+    /// Kind of text chunk to write
+    #[arg(long, value_enum, default_value_t = TextKind::Auto)]
+    pub kind: TextKind,
+
+    /// Language of the text for iTXt chunks, e.g. en or pt-BR
+    #[arg(short, long)]
+    pub language: Option<String>,
+
+    /// Keyword translated into the language of the text for iTXt chunks
+    #[arg(long)]
+    pub translated_keyword: Option<String>
+}
+
+#[derive(Debug, Args)]
+pub struct MetaDeleteArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Keyword, e.g. Author, Description or Comment
+    #[arg(short, long)]
+    pub keyword: String
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encryption {
+    /// No encryption
+    None,
+
+    /// Encrypted with a password
+    Password,
+
+    /// Encrypted to recipients
+    Recipients
+}
+
+#[derive(Debug, Args)]
+pub struct CapacityArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf,
+
+    /// Path to file to be hidden, its name is counted in the overhead & each method reports whether it fits
+    #[arg(long)]
+    pub input_file: Option<PathBuf>,
+
+    /// Encryption the estimates account for
+    #[arg(long, value_enum, default_value_t = Encryption::None)]
+    pub encryption: Encryption,
+
+    /// Number of recipients when encrypting to recipients
+    #[arg(long, default_value_t = 1)]
+    pub recipient_count: usize,
+
+    /// Error correction percent the estimates account for, as given to encode --ecc
+    #[arg(long)]
+    pub ecc: Option<u8>,
+
+    /// Print the estimates as JSON
+    #[arg(long)]
+    pub json: bool
+}
+
+#[derive(Debug, Args)]
+pub struct DetectArgs {
+
+    /// Path to PNG file
+    #[arg(short, long)]
+    pub file_path: PathBuf
+}
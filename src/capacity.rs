@@ -0,0 +1,288 @@
+use std::fmt;
+
+use crate::Result;
+use crate::chunk::Chunk;
+use crate::crypto;
+use crate::disguise::TextEntry;
+use crate::ecc;
+use crate::envelope;
+use crate::ihdr::InterlaceMethod;
+use crate::lsb::{self, Channels, LsbOptions};
+use crate::palette;
+use crate::payload::Payload;
+use crate::png::Png;
+
+const LSB_BITS: [u8; 3] = [1, 2, 3];    // bits per channel estimated for the lsb method
+const MATRIX_BITS: [u8; 3] = [2, 3, 4]; // Hamming matrix encoding bits estimated for the lsb method
+
+// How the hidden data is encrypted, which adds a header & tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    None,
+    Password,
+    Recipients(usize)
+}
+
+// Bytes wrapped around the hidden data before it is embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overhead {
+    pub payload_header: usize,
+    pub encryption: usize,
+    pub envelope: usize,
+    pub ecc_parity: Option<usize>   // parity symbols per codeword, which grow with the data rather than by a fixed amount
+}
+
+impl Overhead {
+    // Overhead of hiding payload, whose data is not counted, with encryption & optional error correction
+    pub fn new(payload: &Payload, encryption: Encryption, ecc_parity: Option<usize>) -> Overhead {
+        Overhead {
+            payload_header: payload.header_length(),
+            encryption: match encryption {
+                Encryption::None => 0,
+                Encryption::Password => crypto::password_overhead(),
+                Encryption::Recipients(count) => crypto::recipients_overhead(count)
+            },
+            envelope: envelope::HEADER_LENGTH,
+            ecc_parity
+        }
+    }
+
+    // Fixed overhead, without error correction
+    pub fn total(&self) -> usize {
+        self.payload_header + self.encryption + self.envelope
+    }
+
+    // Largest data that fits in embedded bytes once the overhead is added, None if not even the overhead fits
+    fn room(&self, embedded: usize) -> Option<usize> {
+        let room = embedded.checked_sub(self.total())?;
+        let Some(parity) = self.ecc_parity else {
+            return Some(room);
+        };
+
+        // protected length only grows with the data, so search for the longest data that fits
+        let fits = |length: usize| ecc::protected_length(length + self.total(), parity) <= embedded;
+        if !fits(0) {
+            return None;
+        }
+        let (mut low, mut high) = (0, room);
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            if fits(middle) { low = middle } else { high = middle - 1 }
+        }
+        Some(low)
+    }
+}
+
+// Room available to hidden data with one embedding method
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Capacity {
+    Bytes(usize),
+    Unlimited,
+    Unavailable(String)
+}
+
+impl fmt::Display for Capacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Capacity::Bytes(bytes) => write!(f, "{} bytes", bytes),
+            Capacity::Unlimited => write!(f, "unlimited"),
+            Capacity::Unavailable(reason) => write!(f, "unavailable: {}", reason)
+        }
+    }
+}
+
+// Largest uncompressed message or file each embedding method can hide
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub method: String,
+    pub capacity: Capacity
+}
+
+impl Estimate {
+    fn new(method: &str, embedded: Result<usize>, overhead: &Overhead) -> Estimate {
+        let capacity = match embedded.map(|bytes| overhead.room(bytes)) {
+            Ok(Some(bytes)) => Capacity::Bytes(bytes),
+            Ok(None) => Capacity::Unavailable("Image is too small".to_string()),
+            Err(e) => Capacity::Unavailable(e.to_string())
+        };
+        Estimate { method: method.to_string(), capacity }
+    }
+
+    // Check if data of this many bytes fits, without compression
+    pub fn fits(&self, length: usize) -> bool {
+        match self.capacity {
+            Capacity::Bytes(bytes) => length <= bytes,
+            Capacity::Unlimited => true,
+            Capacity::Unavailable(_) => false
+        }
+    }
+}
+
+// Estimate the capacity of every embedding method for PNG
+pub fn estimate(png: &Png, overhead: &Overhead) -> Vec<Estimate> {
+    let mut estimates = vec![
+        Estimate::new("chunk", Ok(Chunk::MAX_LENGTH as usize), overhead),
+        Estimate::new("text-comment", Ok(TextEntry::Comment.capacity(png)), overhead),
+        Estimate::new("text-xmp", Ok(TextEntry::Xmp.capacity(png)), overhead),
+        Estimate { method: "trailer".to_string(), capacity: Capacity::Unlimited }
+    ];
+
+    for bits in LSB_BITS {
+        let options = LsbOptions { bits_per_channel: bits, ..LsbOptions::default() };
+        estimates.push(Estimate::new(&format!("lsb-{}", bits), lsb::capacity(png, &options), overhead));
+    }
+    for matrix_bits in MATRIX_BITS {
+        let options = LsbOptions { matrix_bits: Some(matrix_bits), ..LsbOptions::default() };
+        estimates.push(Estimate::new(&format!("lsb-matrix-{}", matrix_bits), lsb::capacity(png, &options), overhead));
+    }
+
+    let alpha = Channels { red: false, green: false, blue: false, alpha: true };
+    let alpha_only = |translucent_only| lsb::capacity(png, &LsbOptions { channels: alpha, translucent_only, ..LsbOptions::default() });
+    estimates.push(Estimate::new("lsb-alpha", alpha_only(false), overhead));
+    estimates.push(Estimate::new("alpha-translucent", alpha_only(true), overhead));
+    estimates.push(Estimate::new("palette", palette::capacity(png), overhead));
+
+    estimates
+}
+
+// Describe the image geometry & estimates as a JSON object
+pub fn to_json(png: &Png, estimates: &[Estimate], overhead: &Overhead, input_length: Option<usize>) -> String {
+    let methods: Vec<String> = estimates
+        .iter()
+        .map(|estimate| {
+            let mut fields = vec![format!("\"method\": {}", json_string(&estimate.method))];
+            match &estimate.capacity {
+                Capacity::Bytes(bytes) => fields.push(format!("\"capacity\": {}", bytes)),
+                Capacity::Unlimited => fields.push("\"capacity\": null, \"unlimited\": true".to_string()),
+                Capacity::Unavailable(reason) => fields.push(format!("\"capacity\": null, \"unavailable\": {}", json_string(reason)))
+            }
+            if let Some(length) = input_length {
+                fields.push(format!("\"fits\": {}", estimate.fits(length)));
+            }
+            format!("    {{{}}}", fields.join(", "))
+        })
+        .collect();
+
+    let mut lines = Vec::new();
+    if let Ok(ihdr) = png.ihdr() {
+        lines.push(format!(
+            "  \"image\": {{\"width\": {}, \"height\": {}, \"bit_depth\": {}, \"colour_type\": {}, \"interlaced\": {}}}",
+            ihdr.width(), ihdr.height(), ihdr.bit_depth(), json_string(&ihdr.colour_type().to_string()), ihdr.interlace_method() != InterlaceMethod::None
+        ));
+    }
+    lines.push(format!(
+        "  \"overhead\": {{\"payload_header\": {}, \"encryption\": {}, \"envelope\": {}, \"total\": {}, \"ecc_parity\": {}}}",
+        overhead.payload_header, overhead.encryption, overhead.envelope, overhead.total(),
+        overhead.ecc_parity.map_or("null".to_string(), |parity| parity.to_string())
+    ));
+    if let Some(length) = input_length {
+        lines.push(format!("  \"input_length\": {}", length));
+    }
+    lines.push(format!("  \"methods\": [\n{}\n  ]", methods.join(",\n")));
+    format!("{{\n{}\n}}", lines.join(",\n"))
+}
+
+// Quote & escape text as a JSON string
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c)
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::Ihdr;
+    use crate::pixels::ColourType;
+
+    fn testing_png(colour_type: ColourType) -> Png {
+        let ihdr = Ihdr::new(100, 50, 8, colour_type, InterlaceMethod::None).unwrap();
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new());
+        Png::from_chunks(vec![ihdr.to_chunk(), chunk("IDAT"), chunk("IEND")])
+    }
+
+    fn capacity_of<'a>(estimates: &'a [Estimate], method: &str) -> &'a Capacity {
+        &estimates.iter().find(|estimate| estimate.method == method).unwrap().capacity
+    }
+
+    #[test]
+    fn test_overhead() {
+        let payload = Payload::text("");
+        let overhead = Overhead::new(&payload, Encryption::Password, None);
+        let plain = payload.as_bytes().unwrap();
+        assert_eq!(overhead.total(), plain.len() + crypto::password_overhead() + envelope::HEADER_LENGTH);
+        assert!(Overhead::new(&payload, Encryption::Recipients(2), None).total() > Overhead::new(&payload, Encryption::Recipients(1), None).total());
+    }
+
+    #[test]
+    fn test_ecc_room() {
+        let overhead = Overhead::new(&Payload::text(""), Encryption::None, Some(32));
+        let room = overhead.room(5000).unwrap();
+        assert!(ecc::protected_length(room + overhead.total(), 32) <= 5000);
+        assert!(ecc::protected_length(room + 1 + overhead.total(), 32) > 5000);
+        assert_eq!(overhead.room(ecc::protected_length(overhead.total(), 32) - 1), None);
+    }
+
+    #[test]
+    fn test_lsb_estimates() {
+        let png = testing_png(ColourType::TruecolourAlpha);
+        let overhead = Overhead::new(&Payload::text(""), Encryption::None, None);
+        let estimates = estimate(&png, &overhead);
+
+        // 5000 pixels with 3 channels or just alpha, less the length prefix & overhead
+        assert_eq!(capacity_of(&estimates, "lsb-1"), &Capacity::Bytes(5000 * 3 / 8 - 4 - overhead.total()));
+        assert_eq!(capacity_of(&estimates, "lsb-2"), &Capacity::Bytes(5000 * 3 * 2 / 8 - 4 - overhead.total()));
+        // groups of 7 samples carry 3 bits
+        assert_eq!(capacity_of(&estimates, "lsb-matrix-3"), &Capacity::Bytes(5000 * 3 / 7 * 3 / 8 - 4 - overhead.total()));
+        assert_eq!(capacity_of(&estimates, "lsb-alpha"), &Capacity::Bytes(5000 / 8 - 4 - overhead.total()));
+        assert_eq!(capacity_of(&estimates, "trailer"), &Capacity::Unlimited);
+
+        let png = testing_png(ColourType::Truecolour);
+        let estimates = estimate(&png, &overhead);
+        assert_eq!(capacity_of(&estimates, "lsb-alpha"), &Capacity::Unavailable("Image has no alpha channel".to_string()));
+        assert!(matches!(capacity_of(&estimates, "palette"), Capacity::Unavailable(_)));
+
+        // a long filename leaves no room in the alpha channel
+        let overhead = Overhead::new(&Payload::file(Some("a".repeat(600)), Vec::new()), Encryption::None, None);
+        let estimates = estimate(&testing_png(ColourType::TruecolourAlpha), &overhead);
+        assert_eq!(capacity_of(&estimates, "lsb-alpha"), &Capacity::Unavailable("Image is too small".to_string()));
+
+        let png = testing_png(ColourType::Indexed);
+        let estimates = estimate(&png, &overhead);
+        assert!(matches!(capacity_of(&estimates, "lsb-1"), Capacity::Unavailable(_)));
+        assert!(matches!(capacity_of(&estimates, "chunk"), Capacity::Bytes(_)));
+    }
+
+    #[test]
+    fn test_json_output() {
+        let overhead = Overhead { payload_header: 10, encryption: 0, envelope: 49, ecc_parity: Some(24) };
+        let estimates = vec![
+            Estimate { method: "chunk".to_string(), capacity: Capacity::Bytes(100) },
+            Estimate { method: "trailer".to_string(), capacity: Capacity::Unlimited },
+            Estimate { method: "lsb-1".to_string(), capacity: Capacity::Unavailable("needs \"8-bit\"".to_string()) }
+        ];
+        assert_eq!(to_json(&testing_png(ColourType::Truecolour), &estimates, &overhead, Some(150)), [
+            "{",
+            "  \"image\": {\"width\": 100, \"height\": 50, \"bit_depth\": 8, \"colour_type\": \"truecolour\", \"interlaced\": false},",
+            "  \"overhead\": {\"payload_header\": 10, \"encryption\": 0, \"envelope\": 49, \"total\": 59, \"ecc_parity\": 24},",
+            "  \"input_length\": 150,",
+            "  \"methods\": [",
+            "    {\"method\": \"chunk\", \"capacity\": 100, \"fits\": false},",
+            "    {\"method\": \"trailer\", \"capacity\": null, \"unlimited\": true, \"fits\": true},",
+            "    {\"method\": \"lsb-1\", \"capacity\": null, \"unavailable\": \"needs \\\"8-bit\\\"\", \"fits\": false}",
+            "  ]",
+            "}"
+        ].join("\n"));
+    }
+}
@@ -1,8 +1,9 @@
+use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::fmt;
 use crc::crc32::checksum_ieee;
 
-use crate::Result;
+use crate::{Error, Result};
 use crate::chunk_type::ChunkType;
 
 // PNG chunk
@@ -15,6 +16,9 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    pub const OVERHEAD: usize = 12;     // bytes taken by the length, chunk type & CRC fields
+    pub const MAX_LENGTH: u32 = (1 << 31) - 1;  // largest data length allowed by the PNG specification
+
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
         let crc_data = [&chunk_type.bytes(), data.as_slice()].concat();
         let crc = checksum_ieee(&crc_data);
@@ -48,8 +52,9 @@ impl Chunk {
     }
 
     // Convert chunk data to String, returning error if data is not valid UTF-8
+    #[cfg(test)]
     pub fn data_as_string(&self) -> Result<String> {
-        let data_string = String::from_utf8(self.data.clone()).expect("Error converting data to string");
+        let data_string = String::from_utf8(self.data.clone())?;
         Ok(data_string)
     }
 
@@ -68,41 +73,48 @@ impl Chunk {
 }
 
 impl TryFrom<&[u8]> for Chunk {
-    type Error = &'static str;
+    type Error = Error;
 
-    fn try_from(bytes: &[u8]) -> std::result::Result<Self, Self::Error> {
+    // Parse a single chunk, bytes must hold exactly the chunk's length, type, data & CRC fields
+    fn try_from(bytes: &[u8]) -> Result<Self> {
 
-        // read data length
-        let length = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        // make sure length, chunk type & CRC fields are present
+        if bytes.len() < Chunk::OVERHEAD {
+            return Err(Error::TruncatedChunk { offset: 0 });
+        }
 
-        // read chunk type
-        let chunk_type: [u8; 4] = bytes[4..8].try_into().unwrap();
-        let chunk_type = ChunkType::try_from(chunk_type).unwrap();
+        // read data length
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        if length > Chunk::MAX_LENGTH {
+            return Err(Error::LengthOverflow { offset: 0, length });
+        }
 
-        // make sure input chunk type is valid
-        if !chunk_type.is_valid(){
-            return Err("Invalid chunk type")
+        // read chunk type, a chunk with the reserved bit set is still parsed & left for the linter to report
+        let chunk_type = ChunkType::try_from([bytes[4], bytes[5], bytes[6], bytes[7]])?;
+
+        // make sure input length is equal to the size of the data field
+        let data_end = 8 + length as usize;
+        match bytes.len().cmp(&(data_end + 4)) {
+            Ordering::Less => return Err(Error::TruncatedChunk { offset: 0 }),
+            Ordering::Greater => {
+                return Err(Error::InvalidData(format!("Chunk {} is followed by {} unexpected bytes", chunk_type, bytes.len() - data_end - 4)));
+            },
+            Ordering::Equal => {}
         }
 
         // read data
-        let data = Vec::from(&bytes[8..bytes.len()-4]);
-
-        // make sure input length is equal to calculated length
-        if length!=data.len().try_into().unwrap() {
-            return Err("Invalid length")
-        }
+        let data = Vec::from(&bytes[8..data_end]);
 
         // read CRC input
-        let crc = &bytes[bytes.len()-4..];
-        let crc = u32::from_be_bytes(crc.try_into().unwrap());
+        let crc = u32::from_be_bytes([bytes[data_end], bytes[data_end + 1], bytes[data_end + 2], bytes[data_end + 3]]);
 
         // calculate CRC from input chunk type and data
         let crc_data = [&chunk_type.bytes(), data.as_slice()].concat();
         let calculated_crc = checksum_ieee(&crc_data);
 
         // make sure input CRC is equal to calculated CRC
-        if crc!=calculated_crc  {
-              return Err("Invalid CRC")
+        if crc != calculated_crc {
+            return Err(Error::CrcMismatch { chunk_type: chunk_type.to_string(), expected: crc, actual: calculated_crc });
         }
 
         Ok(Chunk {
@@ -119,7 +131,7 @@ impl fmt::Display for Chunk {
         writeln!(f, "Length: {}", self.length())?;
         writeln!(f, "Type: {}", self.chunk_type())?;
         writeln!(f, "Data size: {} bytes", self.data.len())?;
-        writeln!(f, "Crc: {}", self.crc());
+        writeln!(f, "Crc: {}", self.crc())?;
         Ok(())
     }
 }
@@ -232,6 +244,48 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    fn chunk_bytes(length: u32, chunk_type: &[u8], data: &[u8], crc: u32) -> Vec<u8> {
+        [&length.to_be_bytes()[..], chunk_type, data, &crc.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn test_crc_mismatch_error() {
+        let bytes = chunk_bytes(42, b"RuSt", b"This is where your secret message will be!", 2882656333);
+        match Chunk::try_from(bytes.as_ref()) {
+            Err(Error::CrcMismatch { chunk_type, expected, actual }) => {
+                assert_eq!(chunk_type, "RuSt");
+                assert_eq!(expected, 2882656333);
+                assert_eq!(actual, 2882656334);
+            },
+            other => panic!("Expected CRC mismatch, found {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_truncated_chunk_error() {
+        let bytes = chunk_bytes(42, b"RuSt", b"This is where", 2882656334);
+        assert!(matches!(Chunk::try_from(bytes.as_ref()), Err(Error::TruncatedChunk { offset: 0 })));
+        assert!(matches!(Chunk::try_from(&bytes[..7]), Err(Error::TruncatedChunk { offset: 0 })));
+    }
+
+    #[test]
+    fn test_length_overflow_error() {
+        let bytes = chunk_bytes(u32::MAX, b"RuSt", b"", 0);
+        assert!(matches!(Chunk::try_from(bytes.as_ref()), Err(Error::LengthOverflow { length: u32::MAX, .. })));
+    }
+
+    #[test]
+    fn test_invalid_chunk_type_error() {
+        let bytes = chunk_bytes(0, b"Ru1t", b"", 0);
+        assert!(matches!(Chunk::try_from(bytes.as_ref()), Err(Error::InvalidChunkType(_))));
+    }
+
+    #[test]
+    fn test_data_as_string_invalid_utf8() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff, 0xfe]);
+        assert!(chunk.data_as_string().is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
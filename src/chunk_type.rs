@@ -2,6 +2,18 @@ use std::convert::TryFrom;
 use std::str::FromStr;
 use std::fmt;
 
+use crate::Error;
+
+// Chunk types defined by the PNG specification, its extensions & APNG
+const REGISTERED_CHUNK_TYPES: [&str; 33] = [
+    "IHDR", "PLTE", "IDAT", "IEND",
+    "cHRM", "cICP", "gAMA", "iCCP", "mDCV", "cLLI", "sBIT", "sRGB",
+    "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "sPLT", "tIME",
+    "iTXt", "tEXt", "zTXt",
+    "acTL", "fcTL", "fdAT",
+    "oFFs", "pCAL", "sCAL", "sTER", "gIFg", "gIFx", "gIFt", "dSIG"
+];
+
 // PNG chunk type
 #[derive(Debug, PartialEq, Eq)]
 pub struct ChunkType([u8; 4]);
@@ -13,6 +25,7 @@ impl ChunkType {
     }
 
     // Check if chunk type consists only of uppercase & lowercase ASCII letters
+    #[cfg(test)]
     pub fn is_valid(&self) -> bool {
         for i in 0..3 {
             if !self.bytes()[i].is_ascii_alphabetic() || !self.is_reserved_bit_valid() {
@@ -37,7 +50,13 @@ impl ChunkType {
         self.bytes()[2].is_ascii_uppercase()
     }
 
+    // Check if chunk type is defined by the PNG specification or a registered extension
+    pub fn is_registered(&self) -> bool {
+        REGISTERED_CHUNK_TYPES.iter().any(|chunk_type| chunk_type.as_bytes() == self.0)
+    }
+
     // Check if fourth letter is lowercase, inidcating chunk is safe to be copied
+    #[cfg(test)]
     pub fn is_safe_to_copy(&self) -> bool {
         self.bytes()[3].is_ascii_lowercase()
     }
@@ -45,36 +64,27 @@ impl ChunkType {
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = &'static str;
+    type Error = Error;
 
     fn try_from(bytes: [u8; 4]) -> Result<Self, Self::Error> {
-        for i in 0..3 {
-            if !bytes[i].is_ascii_alphabetic() {
-                return Err("Chunk type must only consist of ASCII letters.")
-            }
+        // all four bytes, so the error matches what InvalidChunkType promises
+        if !bytes.iter().all(|byte| byte.is_ascii_alphabetic()) {
+            return Err(Error::InvalidChunkType(bytes.escape_ascii().to_string()))
         }
         Ok(ChunkType(bytes))
     }
 }
 
 impl FromStr for ChunkType {
-    type Err = &'static str;
+    type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.len() > 4 {
-            return Err("Chunk type must be 4 bytes long.");
-        }
-
-        for c in s.chars() {
-            if !c.is_ascii_alphabetic(){
-                return Err("Chunk type must only consist of ASCII letters.")
-            }
-        }
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| Error::InvalidChunkType(s.escape_default().to_string()))?;
 
-        let mut bytes = [0; 4];
-        bytes.clone_from_slice(s.as_bytes());
-
-        Ok(ChunkType(bytes))
+        ChunkType::try_from(bytes)
     }
 }
 
@@ -169,6 +179,23 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_invalid_chunk_type_errors() {
+        assert!(matches!(ChunkType::from_str("Ru1t"), Err(Error::InvalidChunkType(t)) if t == "Ru1t"));
+        assert!(matches!(ChunkType::from_str("Ru"), Err(Error::InvalidChunkType(_))));
+        assert!(matches!(ChunkType::from_str("RuStRuSt"), Err(Error::InvalidChunkType(_))));
+        assert!(matches!(ChunkType::try_from([82, 0, 83, 255]), Err(Error::InvalidChunkType(t)) if t == "R\\x00S\\xff"));
+        assert!(matches!(ChunkType::from_str("RuS1"), Err(Error::InvalidChunkType(t)) if t == "RuS1"));
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_registered() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_registered());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_registered());
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_registered());
+        assert!(!ChunkType::from_str("text").unwrap().is_registered());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -1,24 +1,167 @@
-use std::io::Write;
-use std::path::PathBuf;
-use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::fs::{self, File};
 use std::str::FromStr;
 
 use crate::Error;
-use crate::args::{EncodeArgs, DecodeArgs, RemoveArgs, PrintArgs};
+use crate::args::{EncodeArgs, DecodeArgs, RemoveArgs, PrintArgs, KeygenArgs, VerifyArgs, LintArgs, MetaArgs, MetaAction, TextKind, CapacityArgs, DetectArgs, Encryption, Compress, Method, Position, Carrier, Disguise, Camouflage, Interlace};
+use crate::disguise::{self, TextEntry};
+use crate::capacity::{self, Overhead};
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
+use crate::compression::{self, Algorithm};
+use crate::crypto;
+use crate::detect as steganalysis;
+use crate::ecc;
+use crate::envelope::{self, Envelope};
+use crate::fragment;
+use crate::ihdr::InterlaceMethod;
+use crate::keys::{Identity, Recipient, Signer, SigningIdentity};
+use crate::lint::{self as linter, Severity};
+use crate::lsb::{self, Channels, LsbOptions};
+use crate::palette;
+use crate::payload::Payload;
 use crate::png::Png;
+use crate::signature;
+use crate::text::{self, TextChunk};
+use crate::trailer;
+
+const LONG_TEXT_LENGTH: usize = 1024;     // text longer than this is compressed by meta set
 
 // Create PNG struct from file
 pub fn read_png(file_path: PathBuf) -> Result<Png, Error> {
-    Ok(Png::from_file(file_path).unwrap())
+    Png::from_file(file_path)
 }
 
 // Encode secret message within PNG and save the output as a file
 pub fn encode(args: EncodeArgs) -> Result<(), Error>{
-    let mut png = read_png(args.file_path).unwrap();
-    let chunk = Chunk::new(ChunkType::from_str(&args.chunk_type).unwrap(), args.message.into_bytes());
-    png.append_chunk(chunk);
+    let mut png = read_png(args.file_path)?;
+    let payload = match (&args.message, &args.input_file) {
+        (Some(message), _) => Payload::text(message),
+        (None, Some(input_file)) => read_input_file(input_file)?,
+        (None, None) => return Err(Error::Usage("Either a message or an input file is required".to_string()))
+    };
+    let payload = payload.as_bytes()?;
+
+    let (algorithm, compressed) = match args.compress {
+        Compress::Auto => compression::compress_smallest(&payload)?,
+        Compress::None => (Algorithm::None, payload.clone()),
+        Compress::Deflate => (Algorithm::Deflate, compression::compress(&payload, Algorithm::Deflate)?),
+        Compress::Zstd => (Algorithm::Zstd, compression::compress(&payload, Algorithm::Zstd)?)
+    };
+    if algorithm != Algorithm::None && payload.len() > compression::MAX_MESSAGE_LENGTH {
+        return Err(Error::Usage(format!("Message of {} bytes is too large to decompress when decoding, use --compress none", payload.len())));
+    }
+    if algorithm != Algorithm::None {
+        println!("Compressed message from {} to {} bytes with {}", payload.len(), compressed.len(), algorithm);
+    }
+
+    let mut flags = 0;
+    let body = if let Some(password) = &args.password {
+        flags |= envelope::FLAG_ENCRYPTED;
+        crypto::encrypt_with_password(&compressed, password)?
+    } else if !args.recipients.is_empty() {
+        flags |= envelope::FLAG_ENCRYPTED;
+        let recipients = args.recipients.iter().map(|arg| Recipient::from_arg(arg)).collect::<Result<Vec<_>, Error>>()?;
+        crypto::encrypt_to_recipients(&compressed, &recipients)?
+    } else {
+        compressed
+    };
+    if args.method == Method::Chunk && args.carrier == Carrier::Chunk && args.max_chunk_size.is_some() {
+        flags |= envelope::FLAG_FRAGMENTED;
+    }
+    let mut data = Envelope::seal(flags, algorithm, &payload, body).as_bytes();
+    if let Some(percent) = args.ecc {
+        let protected = ecc::protect(&data, ecc::parity_for_percent(percent)?);
+        println!("Added {} bytes of error correction", protected.len() - data.len());
+        data = protected;
+    }
+
+    // converted before embedding so pixels are written & signed in their final form
+    let interlace_method = match args.interlace {
+        Interlace::Preserve => None,
+        Interlace::None => Some(InterlaceMethod::None),
+        Interlace::Adam7 => Some(InterlaceMethod::Adam7)
+    };
+    if let Some(interlace_method) = interlace_method.filter(|&method| png.ihdr().is_ok_and(|ihdr| ihdr.interlace_method() != method)) {
+        png.set_interlace_method(interlace_method)?;
+        println!("Converted image data to interlace method {}", interlace_method);
+    }
+
+    match args.method {
+        Method::Chunk if args.carrier == Carrier::Text => {
+            if args.sign_key.is_some() || args.max_chunk_size.is_some() {
+                return Err(Error::Usage("Signing & fragmenting are not supported for the text carrier".to_string()));
+            }
+            let entry = match args.disguise_as {
+                Disguise::Xmp => TextEntry::Xmp,
+                Disguise::Comment => TextEntry::Comment
+            };
+            disguise::hide(&mut png, &data, entry)?;
+            println!("Disguised message as {} metadata", entry.keyword());
+        },
+        Method::Chunk if args.carrier == Carrier::Trailer => {
+            if args.sign_key.is_some() || args.max_chunk_size.is_some() {
+                return Err(Error::Usage("Signing & fragmenting are not supported for the trailer carrier".to_string()));
+            }
+            let camouflage = match args.camouflage {
+                Camouflage::None => trailer::Camouflage::None,
+                Camouflage::Zip => trailer::Camouflage::Zip
+            };
+            trailer::hide(&mut png, &data, camouflage)?;
+            println!("Stored {} bytes after IEND", png.trailer().len());
+        },
+        Method::Chunk => {
+            let chunk_type = args.chunk_type.ok_or_else(|| Error::Usage("Chunk type is required for the chunk method".to_string()))?;
+            if let Some(max_chunk_size) = args.max_chunk_size {
+                if args.sign_key.is_some() {
+                    return Err(Error::Usage("Signing is not supported for fragmented messages".to_string()));
+                }
+                let fragments = fragment::split(&data, max_chunk_size)?;
+                fragment::insert(&mut png, &chunk_type, &fragments, args.interleave)?;
+                println!("Split message into {} chunks", fragments.len());
+            } else {
+                let chunk = Chunk::new(ChunkType::from_str(&chunk_type)?, data);
+                match args.position {
+                    Position::Auto => png.insert_chunk(chunk),
+                    Position::BeforeIdat => png.insert_before("IDAT", chunk)?,
+                    Position::AfterIdat => png.insert_after("IDAT", chunk)?
+                }
+            }
+
+            if let Some(sign_key_path) = args.sign_key {
+                let key = SigningIdentity::from_file(sign_key_path)?;
+                let signature = signature::sign(&png, &chunk_type, &key, args.sign_critical)?;
+                png.insert_chunk(signature);
+            }
+        },
+        Method::Lsb | Method::Alpha => {
+            if args.sign_key.is_some() {
+                return Err(Error::Usage("Signing is only supported for the chunk method".to_string()));
+            }
+            let options = lsb_options(args.method, &args.channels, args.bits, args.translucent_only, args.matrix, args.password.as_deref())?;
+            let changes = lsb::embed(&mut png, &data, &options)?;
+            if options.scatter_key.is_some() {
+                println!("Scattered message bits across the image in an order derived from the password");
+            }
+            println!("Used {} of {} bytes available in image pixels", data.len(), lsb::capacity(&png, &options)?);
+            println!(
+                "Changed {} of {} carrier samples to embed {} bits, {:.3} changes per embedded bit",
+                changes.changed, changes.carriers, changes.bits, changes.per_bit()
+            );
+        },
+        Method::Palette => {
+            if args.sign_key.is_some() {
+                return Err(Error::Usage("Signing is only supported for the chunk method".to_string()));
+            }
+            let scatter_key = args.password.as_deref().map(crypto::derive_scatter_key).transpose()?;
+            palette::embed(&mut png, &data, scatter_key)?;
+            if scatter_key.is_some() {
+                println!("Scattered message bits across the image in an order derived from the password");
+            }
+            println!("Used {} of {} bytes available in palette colour pairs", data.len(), palette::capacity(&png)?);
+        }
+    }
 
     let mut png_file = File::create(args.output_file_path)?;
     png_file.write_all(&png.as_bytes())?;
@@ -31,9 +174,50 @@ pub fn encode(args: EncodeArgs) -> Result<(), Error>{
 
 // Extract secret message from PNG if it exists
 pub fn decode(args: DecodeArgs) -> Result<(), Error> {
-    let png = read_png(args.file_path).unwrap();
-    if let Some(chunk) = png.chunk_by_type(&args.chunk_type) {
-        println!("Hidden message: {}", chunk.data_as_string().unwrap());
+    let png = read_damaged_png(&args.file_path)?;
+    if args.scan {
+        return scan(&png, &args);
+    }
+
+    let data = match args.method {
+        Method::Chunk if args.carrier == Carrier::Text => disguise::reveal(&png),
+        Method::Chunk if args.carrier == Carrier::Trailer => trailer::reveal(&png),
+        Method::Chunk => {
+            let chunk_type = args.chunk_type.as_ref().ok_or_else(|| Error::Usage("Chunk type is required for the chunk method".to_string()))?;
+            let chunks = png.chunks_by_type(chunk_type);
+            if chunks.iter().any(|chunk| fragment::is_fragment(chunk.data())) {
+                Some(fragment::reassemble(chunks.iter().map(|chunk| chunk.data()))?)
+            } else {
+                // prefer our envelope over unrelated chunks of the same type
+                chunks
+                    .iter()
+                    .find(|chunk| envelope::holds_envelope(chunk.data()))
+                    .or(chunks.first())
+                    .map(|chunk| chunk.data().to_vec())
+            }
+        },
+        Method::Lsb | Method::Alpha => {
+            let options = lsb_options(args.method, &args.channels, args.bits, args.translucent_only, args.matrix, args.password.as_deref())?;
+            let data = lsb::extract(&png, &options);
+            Some(if options.scatter_key.is_some() { scattered_envelope(data)? } else { data? })
+        },
+        Method::Palette => {
+            let scatter_key = args.password.as_deref().map(crypto::derive_scatter_key).transpose()?;
+            let data = palette::extract(&png, scatter_key);
+            Some(if scatter_key.is_some() { scattered_envelope(data)? } else { data? })
+        }
+    };
+
+    if let Some(data) = data {
+        let data = if ecc::is_protected(&data) {
+            let recovered = ecc::recover(&data)?;
+            eprintln!("Error correction fixed {} corrupted bytes", recovered.corrected);
+            recovered.data
+        } else {
+            data
+        };
+        let message = open_envelope(&data, &args)?;
+        write_payload(&Payload::from_bytes(&message)?, args.output_file_path.as_deref())?;
     } else {
         println!("No hidden messages found");
     }
@@ -41,9 +225,168 @@ pub fn decode(args: DecodeArgs) -> Result<(), Error> {
     Ok(())
 }
 
+// Data read from the positions a scatter key picked, which are random bits unless the password is the one used to embed
+fn scattered_envelope(data: Result<Vec<u8>, Error>) -> Result<Vec<u8>, Error> {
+    data.ok()
+        .filter(|data| envelope::holds_envelope(data))
+        .ok_or_else(|| Error::InvalidData("No message envelope found at the positions derived from the password, the password may be wrong".to_string()))
+}
+
+// Read PNG whose ancillary chunks may have been damaged, warning about chunks with a wrong CRC
+fn read_damaged_png(path: &Path) -> Result<Png, Error> {
+    let bytes = fs::read(path)?;
+    let (png, corrupted) = Png::parse_tolerant(&bytes)?;
+    for index in corrupted {
+        eprintln!("Warning: ignored CRC mismatch in chunk {} {}", index, png.chunks()[index].chunk_type());
+    }
+    Ok(png)
+}
+
+// Parse envelope, decrypting, decompressing & verifying its body
+fn open_envelope(data: &[u8], args: &DecodeArgs) -> Result<Vec<u8>, Error> {
+    let envelope = Envelope::from_bytes(data)?;
+    if envelope.has_flag(envelope::FLAG_ENCRYPTED) && !crypto::is_encrypted(&envelope.body) {
+        return Err(Error::InvalidData("Envelope is marked as encrypted but holds no encrypted data".to_string()));
+    }
+    let compressed = decrypt(envelope.body.clone(), args)?;
+    let message = compression::decompress(&compressed, envelope.compression, compression::MAX_MESSAGE_LENGTH)?;
+    envelope.verify(&message)?;
+    Ok(message)
+}
+
+// Describe whether envelope data decodes, and what it holds
+fn describe_envelope(data: &[u8], args: &DecodeArgs) -> String {
+    if ecc::is_protected(data) {
+        return match ecc::recover(data) {
+            Ok(recovered) => format!("{} after correcting {} bytes", describe_envelope(&recovered.data, args), recovered.corrected),
+            Err(e) => format!("does not decode: {}", e)
+        };
+    }
+    match open_envelope(data, args).and_then(|message| Payload::from_bytes(&message)) {
+        Ok(payload) if payload.is_text() => format!("decodes to a {} byte message", payload.data.len()),
+        Ok(payload) => format!(
+            "decodes to file {} ({} bytes, {})",
+            payload.filename.as_deref().unwrap_or("unnamed"),
+            payload.data.len(),
+            payload.content_type
+        ),
+        Err(e) => format!("does not decode: {}", e)
+    }
+}
+
+// Report every non-standard chunk that may hold hidden data & whether it decodes
+fn scan(png: &Png, args: &DecodeArgs) -> Result<(), Error> {
+    let offsets = png.chunk_offsets();
+    let mut fragmented_types: Vec<String> = Vec::new();
+    let mut candidates = 0;
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type().is_registered() || chunk.chunk_type().to_string() == signature::SIGNATURE_CHUNK_TYPE {
+            continue;
+        }
+        candidates += 1;
+
+        let chunk_type = chunk.chunk_type().to_string();
+        let status = if envelope::holds_envelope(chunk.data()) {
+            describe_envelope(chunk.data(), args)
+        } else if fragment::is_fragment(chunk.data()) {
+            if !fragmented_types.contains(&chunk_type) {
+                fragmented_types.push(chunk_type.clone());
+            }
+            match fragment::Fragment::from_bytes(chunk.data()) {
+                Ok(fragment) => format!("fragment {} of {} of payload {:08x}", fragment.index + 1, fragment.total, fragment.payload_id),
+                Err(e) => format!("does not decode: {}", e)
+            }
+        } else {
+            "not an imgcrypt payload".to_string()
+        };
+
+        println!("Chunk {} {} at offset {}, {} bytes: {}", index, chunk_type, offsets[index], chunk.length(), status);
+    }
+
+    for chunk_type in fragmented_types {
+        let fragments = png.chunks_by_type(&chunk_type);
+        let status = match fragment::reassemble(fragments.iter().map(|chunk| chunk.data())) {
+            Ok(data) => describe_envelope(&data, args),
+            Err(e) => format!("does not decode: {}", e)
+        };
+        println!("Fragments in {} chunks: {}", chunk_type, status);
+    }
+
+    if !png.trailer().is_empty() {
+        let status = match trailer::reveal(png) {
+            Some(data) => describe_envelope(&data, args),
+            None => "not an imgcrypt payload".to_string()
+        };
+        println!("Trailing data at offset {}, {} bytes: {}", png.as_bytes().len() - png.trailer().len(), png.trailer().len(), status);
+    } else if candidates == 0 {
+        println!("No non-standard chunks found");
+    }
+
+    Ok(())
+}
+
+// Decrypt data if it is encrypted, using the password or identity from the command line
+fn decrypt(data: Vec<u8>, args: &DecodeArgs) -> Result<Vec<u8>, Error> {
+    match crypto::scheme(&data) {
+        Some(crypto::SCHEME_PASSWORD) => {
+            let password = args.password.as_ref().ok_or_else(|| Error::Usage("Hidden message is encrypted, supply a password to decrypt it".to_string()))?;
+            crypto::decrypt_with_password(&data, password)
+        },
+        Some(crypto::SCHEME_RECIPIENTS) => {
+            let identity_path = args.identity.as_ref().ok_or_else(|| Error::Usage("Hidden message is encrypted to recipients, supply an identity file to decrypt it".to_string()))?;
+            let identity = Identity::from_file(identity_path)?;
+            crypto::decrypt_with_identity(&data, &identity)
+        },
+        Some(scheme) => Err(Error::InvalidData(format!("Unsupported encryption scheme {}", scheme))),
+        None => Ok(data)
+    }
+}
+
+// Read file to be hidden, or stdin if path is -
+fn read_input_file(path: &Path) -> Result<Payload, Error> {
+    if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin().read_to_end(&mut data)?;
+        return Ok(Payload::file(None, data));
+    }
+
+    let filename = path.file_name().map(|name| name.to_string_lossy().to_string());
+    Ok(Payload::file(filename, fs::read(path)?))
+}
+
+// Write extracted payload to file or stdout, printing text & describing binary data when no output file is given
+fn write_payload(payload: &Payload, output_file_path: Option<&Path>) -> Result<(), Error> {
+    match output_file_path {
+        Some(path) if path == Path::new("-") => io::stdout().write_all(&payload.data)?,
+        Some(path) => {
+            fs::write(path, &payload.data)?;
+            println!("Hidden data written to {} ({} bytes, {})", path.display(), payload.data.len(), payload.content_type);
+        },
+        None if payload.is_text() => println!("Hidden message: {}", String::from_utf8_lossy(&payload.data)),
+        None => {
+            println!("Hidden file: {} ({} bytes, {})", payload.filename.as_deref().unwrap_or("unnamed"), payload.data.len(), payload.content_type);
+            println!("Use --output-file to extract it");
+        }
+    }
+    Ok(())
+}
+
+// Build LSB options from command line arguments, the alpha method ignores channels & a password also scatters the bits
+fn lsb_options(method: Method, channels: &str, bits: u8, translucent_only: bool, matrix_bits: Option<u8>, password: Option<&str>) -> Result<LsbOptions, Error> {
+    let channels = if method == Method::Alpha { "a" } else { channels };
+    Ok(LsbOptions {
+        channels: Channels::from_str(channels)?,
+        bits_per_channel: bits,
+        scatter_key: password.map(crypto::derive_scatter_key).transpose()?,
+        translucent_only,
+        matrix_bits
+    })
+}
+
 // Remove chunk from PNG and save the output as a file
 pub fn remove(args: RemoveArgs) -> Result<(), Error>{
-    let mut png = read_png(args.file_path.clone()).unwrap();
+    let mut png = read_png(args.file_path.clone())?;
     png.remove_chunk(&args.chunk_type)?;
 
     let mut png_file = File::create(args.file_path)?;
@@ -56,12 +399,234 @@ pub fn remove(args: RemoveArgs) -> Result<(), Error>{
 
 // Print PNG chunks
 pub fn print(args: PrintArgs) -> Result<(), Error>{
-    let png = read_png(args.file_path).unwrap();
-    println!("PNG: {}", png.to_string());
+    let png = read_png(args.file_path)?;
+    println!("PNG: {}", png);
 
     for chunk in png.chunks().iter() {
         println!("{}", chunk)
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Generate X25519 identity or Ed25519 signing key & write it and its public key to files
+pub fn keygen(args: KeygenArgs) -> Result<(), Error>{
+    let public_file_path = args.output_file_path.with_extension("pub");
+
+    // replacing a secret key makes everything encrypted to or signed with it unusable
+    let exists = |e: Error| match e {
+        Error::Io(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            Error::Usage(format!("{} already exists, use --force to overwrite it", args.output_file_path.display()))
+        },
+        e => e
+    };
+
+    let public_key = if args.signing {
+        let key = SigningIdentity::generate();
+        key.write_to_file(&args.output_file_path, args.force).map_err(exists)?;
+        key.signer().write_to_file(&public_file_path)?;
+        key.signer().to_string()
+    } else {
+        let identity = Identity::generate();
+        identity.write_to_file(&args.output_file_path, args.force).map_err(exists)?;
+        identity.recipient().write_to_file(&public_file_path)?;
+        identity.recipient().to_string()
+    };
+
+    println!("Secret key written to {}", args.output_file_path.display());
+    println!("Public key written to {}", public_file_path.display());
+    println!("Public key: {}", public_key);
+
+    Ok(())
+}
+
+// Verify signatures embedded in PNG, returning error if any signature is invalid
+pub fn verify(args: VerifyArgs) -> Result<(), Error>{
+    let png = read_png(args.file_path)?;
+    let expected_signer = args.signer.as_deref().map(Signer::from_arg).transpose()?;
+
+    let reports = signature::verify(&png)?;
+    if reports.is_empty() {
+        return Err("No signatures found".into());
+    }
+
+    let mut all_valid = true;
+    for report in reports.iter() {
+        let trusted = expected_signer.is_none_or(|signer| signer == report.signer);
+        all_valid &= report.valid && trusted;
+
+        println!("Signature over chunk {}", report.payload_type);
+        println!("Signer: {}", report.signer);
+        println!("Valid: {}", if report.valid { "yes" } else { "no" });
+        if expected_signer.is_some() {
+            println!("Expected signer: {}", if trusted { "yes" } else { "no" });
+        }
+        println!("Covered chunks: {}", report.covered.join(", "));
+        println!();
+    }
+
+    if !all_valid {
+        return Err("Signature verification failed".into());
+    }
+
+    Ok(())
+}
+
+// Check PNG against the PNG specification, returning error if any errors, or warnings in strict mode, are found
+pub fn lint(args: LintArgs) -> Result<(), Error>{
+    let png = read_png(args.file_path)?;
+    let findings = linter::lint(&png);
+
+    for finding in findings.iter() {
+        println!("{}", finding);
+    }
+
+    let errors = findings.iter().filter(|finding| finding.severity == Severity::Error).count();
+    let warnings = findings.len() - errors;
+    println!("{} errors, {} warnings", errors, warnings);
+
+    if errors > 0 || (args.strict && warnings > 0) {
+        return Err(Error::InvalidData("PNG does not conform to the specification".to_string()));
+    }
+
+    Ok(())
+}
+
+// List, read & edit textual metadata
+pub fn meta(args: MetaArgs) -> Result<(), Error>{
+    match args.action {
+        MetaAction::List(args) => {
+            let png = read_png(args.file_path)?;
+            let entries = text_chunks(&png);
+            if entries.is_empty() {
+                println!("No text metadata found");
+            }
+            for entry in entries {
+                match entry {
+                    Ok(text_chunk) => println!("{}", text_chunk),
+                    Err(e) => eprintln!("Warning: {}", e)
+                }
+            }
+        },
+        MetaAction::Get(args) => {
+            let png = read_png(args.file_path)?;
+            let text_chunk = text_chunks(&png)
+                .into_iter()
+                .filter_map(|entry| entry.ok())
+                .find(|text_chunk| text_chunk.keyword() == args.keyword)
+                .ok_or(format!("No text metadata with keyword {}", args.keyword))?;
+            println!("{}", text_chunk.text());
+        },
+        MetaAction::Set(args) => {
+            let mut png = read_png(args.file_path.clone())?;
+            let international = args.language.is_some() || args.translated_keyword.is_some();
+            let kind = match args.kind {
+                TextKind::Auto if international || !text::is_latin1(&args.text) => TextKind::Itxt,
+                TextKind::Auto if args.text.len() > LONG_TEXT_LENGTH => TextKind::Ztxt,
+                TextKind::Auto => TextKind::Text,
+                kind => kind
+            };
+            if international && kind != TextKind::Itxt {
+                return Err(Error::Usage("Language & translated keyword are only supported for iTXt chunks".to_string()));
+            }
+
+            let text_chunk = match kind {
+                TextKind::Text | TextKind::Auto => TextChunk::Text { keyword: args.keyword.clone(), text: args.text },
+                TextKind::Ztxt => TextChunk::CompressedText { keyword: args.keyword.clone(), text: args.text },
+                TextKind::Itxt => TextChunk::InternationalText {
+                    keyword: args.keyword.clone(),
+                    compressed: args.text.len() > LONG_TEXT_LENGTH,
+                    language_tag: args.language.unwrap_or_default(),
+                    translated_keyword: args.translated_keyword.unwrap_or_default(),
+                    text: args.text
+                }
+            };
+            let chunk = text_chunk.to_chunk()?;
+
+            remove_text_chunks(&mut png, &args.keyword);
+            png.insert_chunk(chunk);
+            fs::write(&args.file_path, png.as_bytes())?;
+
+            println!("Successfully set {} in {} chunk", args.keyword, text_chunk.chunk_type());
+        },
+        MetaAction::Delete(args) => {
+            let mut png = read_png(args.file_path.clone())?;
+            let removed = remove_text_chunks(&mut png, &args.keyword);
+            if removed == 0 {
+                return Err(format!("No text metadata with keyword {}", args.keyword).into());
+            }
+            fs::write(&args.file_path, png.as_bytes())?;
+
+            println!("Successfully removed {} text chunks with keyword {}", removed, args.keyword);
+        }
+    }
+
+    Ok(())
+}
+
+// Report the largest message or file each embedding method can hide in PNG
+pub fn capacity(args: CapacityArgs) -> Result<(), Error> {
+    let png = read_png(args.file_path)?;
+    let input = args.input_file.as_deref().map(read_input_file).transpose()?;
+    let encryption = match args.encryption {
+        Encryption::None => capacity::Encryption::None,
+        Encryption::Password => capacity::Encryption::Password,
+        Encryption::Recipients => capacity::Encryption::Recipients(args.recipient_count)
+    };
+    // without an input file, assume a message typed on the command line
+    let ecc_parity = args.ecc.map(ecc::parity_for_percent).transpose()?;
+    let overhead = Overhead::new(input.as_ref().unwrap_or(&Payload::text("")), encryption, ecc_parity);
+    let input_length = input.as_ref().map(|payload| payload.data.len());
+    let estimates = capacity::estimate(&png, &overhead);
+
+    if args.json {
+        println!("{}", capacity::to_json(&png, &estimates, &overhead, input_length));
+        return Ok(());
+    }
+
+    let ihdr = png.ihdr()?;
+    println!("Image: {}x{}, {}-bit {}", ihdr.width(), ihdr.height(), ihdr.bit_depth(), ihdr.colour_type());
+    println!(
+        "Overhead: {} bytes (payload header {}, encryption {}, envelope {})",
+        overhead.total(), overhead.payload_header, overhead.encryption, overhead.envelope
+    );
+    if let Some(parity) = ecc_parity {
+        println!("Error correction: {} parity bytes in every block of up to {} bytes", parity, ecc::CODEWORD_LENGTH);
+    }
+    if let Some(length) = input_length {
+        println!("Input: {} bytes", length);
+    }
+    for estimate in &estimates {
+        let fits = match input_length {
+            Some(length) if estimate.fits(length) => ", fits",
+            Some(_) => ", does not fit",
+            None => ""
+        };
+        println!("{:<19}{}{}", estimate.method, estimate.capacity, fits);
+    }
+
+    Ok(())
+}
+
+// Run steganalysis on PNG, printing the suspicion score & the evidence of each test
+pub fn detect(args: DetectArgs) -> Result<(), Error> {
+    let png = read_png(args.file_path)?;
+    let report = steganalysis::detect(&png);
+
+    println!("Suspicion: {:.0}% ({})", report.score() * 100.0, report.level());
+    for evidence in &report.evidence {
+        println!("{}", evidence);
+    }
+
+    Ok(())
+}
+
+// Parse every text chunk of PNG in file order
+fn text_chunks(png: &Png) -> Vec<Result<TextChunk, Error>> {
+    png.chunks().iter().filter(|chunk| text::is_text_chunk(chunk)).map(TextChunk::try_from).collect()
+}
+
+// Remove text chunks with keyword, returning the number removed
+fn remove_text_chunks(png: &mut Png, keyword: &str) -> usize {
+    png.retain_chunks(|chunk| !text::is_text_chunk(chunk) || TextChunk::try_from(chunk).map_or(true, |text_chunk| text_chunk.keyword() != keyword))
+}
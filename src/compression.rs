@@ -0,0 +1,133 @@
+use std::fmt;
+use std::io::{Read, Write};
+
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::{Error, Result};
+
+const ZSTD_LEVEL: i32 = 19;
+pub const MAX_MESSAGE_LENGTH: usize = 256 << 20;    // largest decompressed message, so a small compression bomb cannot exhaust memory
+pub const MAX_TEXT_LENGTH: usize = 8 << 20;         // largest decompressed text chunk, libpng's default chunk allocation limit
+
+// Compression algorithm applied to a payload before it is encrypted & embedded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    None = 0,
+    Deflate = 1,
+    Zstd = 2
+}
+
+impl TryFrom<u8> for Algorithm {
+    type Error = Error;
+
+    fn try_from(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(Algorithm::None),
+            1 => Ok(Algorithm::Deflate),
+            2 => Ok(Algorithm::Zstd),
+            _ => Err(Error::InvalidData(format!("Unknown compression algorithm {}", byte)))
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::None => write!(f, "none"),
+            Algorithm::Deflate => write!(f, "deflate"),
+            Algorithm::Zstd => write!(f, "zstd")
+        }
+    }
+}
+
+// Compress data with algorithm
+pub fn compress(data: &[u8], algorithm: Algorithm) -> Result<Vec<u8>> {
+    match algorithm {
+        Algorithm::None => Ok(data.to_vec()),
+        Algorithm::Deflate => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        },
+        Algorithm::Zstd => Ok(zstd::encode_all(data, ZSTD_LEVEL)?)
+    }
+}
+
+// Compress data with whichever algorithm gives the smallest output, leaving it uncompressed if nothing is gained
+pub fn compress_smallest(data: &[u8]) -> Result<(Algorithm, Vec<u8>)> {
+    let mut best = (Algorithm::None, data.to_vec());
+    for algorithm in [Algorithm::Deflate, Algorithm::Zstd] {
+        let compressed = compress(data, algorithm)?;
+        if compressed.len() < best.1.len() {
+            best = (algorithm, compressed);
+        }
+    }
+    Ok(best)
+}
+
+// Reverse compress, failing rather than producing more than limit bytes
+pub fn decompress(data: &[u8], algorithm: Algorithm, limit: usize) -> Result<Vec<u8>> {
+    let invalid = |e: std::io::Error| Error::InvalidData(format!("Invalid {} compressed data: {}", algorithm, e));
+    let decoder: Box<dyn Read + '_> = match algorithm {
+        Algorithm::None => return Ok(data.to_vec()),
+        Algorithm::Deflate => Box::new(ZlibDecoder::new(data)),
+        Algorithm::Zstd => Box::new(zstd::Decoder::new(data).map_err(invalid)?)
+    };
+
+    // one byte past the limit tells a stream that fits exactly from one that would keep going
+    let mut decompressed = Vec::new();
+    decoder.take(limit as u64 + 1).read_to_end(&mut decompressed).map_err(invalid)?;
+    if decompressed.len() > limit {
+        return Err(Error::InvalidData(format!("{} compressed data expands beyond the {} byte limit", algorithm, limit)));
+    }
+    Ok(decompressed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_text() -> Vec<u8> {
+        "This is where your secret message will be! ".repeat(50).into_bytes()
+    }
+
+    #[test]
+    fn test_compress_roundtrip() {
+        for algorithm in [Algorithm::None, Algorithm::Deflate, Algorithm::Zstd] {
+            let compressed = compress(&testing_text(), algorithm).unwrap();
+            assert_eq!(decompress(&compressed, algorithm, MAX_MESSAGE_LENGTH).unwrap(), testing_text());
+        }
+    }
+
+    #[test]
+    fn test_compress_smallest() {
+        let (algorithm, compressed) = compress_smallest(&testing_text()).unwrap();
+        assert_ne!(algorithm, Algorithm::None);
+        assert!(compressed.len() < testing_text().len() / 10);
+
+        // too short to benefit from compression
+        assert_eq!(compress_smallest(b"Hi").unwrap(), (Algorithm::None, b"Hi".to_vec()));
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        // a megabyte of zeros compresses to a few hundred bytes but must not be inflated past the limit
+        let zeros = vec![0; 1 << 20];
+        for algorithm in [Algorithm::Deflate, Algorithm::Zstd] {
+            let compressed = compress(&zeros, algorithm).unwrap();
+            assert!(compressed.len() < 4096);
+            assert_eq!(decompress(&compressed, algorithm, zeros.len()).unwrap(), zeros);
+            assert!(decompress(&compressed, algorithm, zeros.len() - 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_invalid_compressed_data() {
+        assert!(decompress(b"Not compressed", Algorithm::Deflate, MAX_MESSAGE_LENGTH).is_err());
+        assert!(decompress(b"Not compressed", Algorithm::Zstd, MAX_MESSAGE_LENGTH).is_err());
+        assert!(Algorithm::try_from(3).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{Error, Result};
+use crate::keys::{Identity, Recipient};
+
+pub const MAGIC: [u8; 4] = *b"ICRY";    // marks chunk data as encrypted by imgcrypt
+pub const FORMAT_VERSION: u8 = 1;       // version of the encrypted header layout
+pub const SCHEME_PASSWORD: u8 = 1;      // key derived from password with Argon2id
+pub const SCHEME_RECIPIENTS: u8 = 2;    // random key wrapped for each X25519 recipient
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const KEY_LENGTH: usize = 32;
+const TAG_LENGTH: usize = 16;
+const PASSWORD_HEADER_LENGTH: usize = MAGIC.len() + 2 + 12 + SALT_LENGTH + NONCE_LENGTH;
+const STANZA_LENGTH: usize = KEY_LENGTH + TAG_LENGTH;    // wrapped payload key & its tag
+const WRAP_KEY_INFO: &[u8] = b"imgcrypt x25519 key wrap";
+const SCATTER_KEY_SALT: &[u8] = b"imgcrypt lsb scatter";     // fixed as the key must be derivable before anything is read
+
+// Argon2id cost of the scatter key, pinned rather than taken from the defaults as nothing in the image records it
+const SCATTER_KDF_PARAMS: KdfParams = KdfParams { memory_cost: 19456, time_cost: 2, parallelism: 1 };
+
+// Highest Argon2id costs accepted from a header, as they are read from untrusted images before the password is checked
+const MAX_MEMORY_COST: u32 = 1 << 20;   // 1 GiB in KiB
+const MAX_TIME_COST: u32 = 10;
+const MAX_PARALLELISM: u32 = 16;
+
+// Argon2id cost parameters, stored in the header so decoding does not depend on current defaults
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    pub memory_cost: u32,   // memory size in KiB
+    pub time_cost: u32,     // number of iterations
+    pub parallelism: u32    // degree of parallelism
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST
+        }
+    }
+}
+
+// Header stored in front of the ciphertext of a password encrypted payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PasswordHeader {
+    params: KdfParams,
+    salt: [u8; SALT_LENGTH],
+    nonce: [u8; NONCE_LENGTH]
+}
+
+impl PasswordHeader {
+    // Convert header to bytes containing in order: magic, version, scheme, KDF parameters, salt & nonce
+    fn as_bytes(&self) -> Vec<u8> {
+        MAGIC
+            .iter()
+            .chain([FORMAT_VERSION, SCHEME_PASSWORD].iter())
+            .chain(self.params.memory_cost.to_be_bytes().iter())
+            .chain(self.params.time_cost.to_be_bytes().iter())
+            .chain(self.params.parallelism.to_be_bytes().iter())
+            .chain(self.salt.iter())
+            .chain(self.nonce.iter())
+            .copied()
+            .collect()
+    }
+
+    // Parse header from the start of encrypted data
+    fn from_bytes(bytes: &[u8]) -> Result<PasswordHeader> {
+        check_header(bytes, SCHEME_PASSWORD)?;
+
+        if bytes.len() < PASSWORD_HEADER_LENGTH {
+            return Err(Error::InvalidData("Encrypted data is truncated".to_string()));
+        }
+
+        let read_u32 = |offset: usize| u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap());
+        let params = KdfParams {
+            memory_cost: read_u32(6),
+            time_cost: read_u32(10),
+            parallelism: read_u32(14)
+        };
+        if params.memory_cost > MAX_MEMORY_COST || params.time_cost > MAX_TIME_COST || params.parallelism > MAX_PARALLELISM {
+            return Err(Error::InvalidData(format!(
+                "Key derivation parameters exceed the limits: {} KiB of memory, {} iterations & parallelism of {}",
+                params.memory_cost, params.time_cost, params.parallelism
+            )));
+        }
+
+        Ok(PasswordHeader {
+            params,
+            salt: bytes[18..18 + SALT_LENGTH].try_into().unwrap(),
+            nonce: bytes[18 + SALT_LENGTH..PASSWORD_HEADER_LENGTH].try_into().unwrap()
+        })
+    }
+}
+
+// Check if data starts with an imgcrypt encryption header
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+// Encryption scheme of encrypted data, if any
+pub fn scheme(data: &[u8]) -> Option<u8> {
+    if is_encrypted(data) {
+        data.get(MAGIC.len() + 1).copied()
+    } else {
+        None
+    }
+}
+
+// Make sure data has a header of the expected version & scheme
+fn check_header(bytes: &[u8], scheme: u8) -> Result<()> {
+    if bytes.len() < MAGIC.len() + 2 || !is_encrypted(bytes) {
+        return Err(Error::InvalidData("Data is not encrypted by imgcrypt".to_string()));
+    }
+
+    let version = bytes[MAGIC.len()];
+    if version != FORMAT_VERSION {
+        return Err(Error::InvalidData(format!("Unsupported encryption format version {}", version)));
+    }
+
+    if bytes[MAGIC.len() + 1] != scheme {
+        return Err(Error::InvalidData(format!("Unsupported encryption scheme {}", bytes[MAGIC.len() + 1])));
+    }
+
+    Ok(())
+}
+
+// Derive a 256-bit key from password & salt using Argon2id
+fn derive_key(password: &str, salt: &[u8], params: KdfParams) -> Result<[u8; KEY_LENGTH]> {
+    let argon2_params = Params::new(params.memory_cost, params.time_cost, params.parallelism, Some(KEY_LENGTH))
+        .map_err(|e| Error::InvalidData(format!("Invalid key derivation parameters: {}", e)))?;
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, argon2_params);
+
+    let mut key = [0; KEY_LENGTH];
+    argon2
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+
+    Ok(key)
+}
+
+// Number of bytes encrypt_with_password adds to the plaintext
+pub fn password_overhead() -> usize {
+    PASSWORD_HEADER_LENGTH + TAG_LENGTH
+}
+
+// Number of bytes encrypt_to_recipients adds to the plaintext for count recipients
+pub fn recipients_overhead(count: usize) -> usize {
+    MAGIC.len() + 2 + KEY_LENGTH + 1 + count * STANZA_LENGTH + NONCE_LENGTH + TAG_LENGTH
+}
+
+// Derive the key seeding the order in which LSB embedding visits image samples
+pub fn derive_scatter_key(password: &str) -> Result<[u8; KEY_LENGTH]> {
+    derive_key(password, SCATTER_KEY_SALT, SCATTER_KDF_PARAMS)
+}
+
+// Encrypt plaintext with a key derived from password, using default KDF parameters
+pub fn encrypt_with_password(plaintext: &[u8], password: &str) -> Result<Vec<u8>> {
+    encrypt_with_password_params(plaintext, password, KdfParams::default())
+}
+
+// Encrypt plaintext with a key derived from password, returning header followed by ciphertext & tag
+pub fn encrypt_with_password_params(plaintext: &[u8], password: &str, params: KdfParams) -> Result<Vec<u8>> {
+    let mut salt = [0; SALT_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    OsRng.fill_bytes(&mut nonce);
+
+    let header = PasswordHeader { params, salt, nonce };
+    let header_bytes = header.as_bytes();
+
+    let key = derive_key(password, &salt, params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+
+    // header is authenticated as associated data so KDF parameters, salt & nonce cannot be altered
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header_bytes })
+        .map_err(|_| "Encryption failed")?;
+
+    Ok([header_bytes, ciphertext].concat())
+}
+
+// Decrypt data produced by encrypt_with_password, returning error if password is wrong or data was tampered with
+pub fn decrypt_with_password(data: &[u8], password: &str) -> Result<Vec<u8>> {
+    let header = PasswordHeader::from_bytes(data)?;
+    let (header_bytes, ciphertext) = data.split_at(PASSWORD_HEADER_LENGTH);
+
+    let key = derive_key(password, &header.salt, header.params)?;
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(&key));
+
+    cipher
+        .decrypt(Nonce::from_slice(&header.nonce), Payload { msg: ciphertext, aad: header_bytes })
+        .map_err(|_| "Decryption failed: wrong password or the data has been tampered with".into())
+}
+
+// Derive the key wrapping the payload key for one recipient from the X25519 shared secret
+fn derive_wrap_key(shared_secret: &[u8], ephemeral: &PublicKey, recipient: &Recipient) -> [u8; KEY_LENGTH] {
+    let salt = [ephemeral.as_bytes().as_slice(), &recipient.bytes()].concat();
+    let mut key = [0; KEY_LENGTH];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(WRAP_KEY_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF output length");
+    key
+}
+
+// Encrypt plaintext with a random key that is wrapped separately for each recipient
+// Layout: magic, version, scheme, ephemeral public key, stanza count, stanzas, nonce, ciphertext & tag
+pub fn encrypt_to_recipients(plaintext: &[u8], recipients: &[Recipient]) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        return Err(Error::Usage("At least one recipient is required".to_string()));
+    }
+    let count = u8::try_from(recipients.len()).map_err(|_| Error::Usage("Too many recipients, maximum is 255".to_string()))?;
+
+    let mut payload_key = [0; KEY_LENGTH];
+    let mut nonce = [0; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut payload_key);
+    OsRng.fill_bytes(&mut nonce);
+
+    let ephemeral = StaticSecret::random_from_rng(OsRng);
+    let ephemeral_public = PublicKey::from(&ephemeral);
+
+    let mut header: Vec<u8> = MAGIC.to_vec();
+    header.extend_from_slice(&[FORMAT_VERSION, SCHEME_RECIPIENTS]);
+    header.extend_from_slice(ephemeral_public.as_bytes());
+    header.push(count);
+
+    for recipient in recipients {
+        let shared_secret = ephemeral.diffie_hellman(recipient.public_key());
+        if !shared_secret.was_contributory() {
+            return Err(Error::InvalidData(format!("Invalid recipient public key {}", recipient)));
+        }
+
+        // each wrapping key is used exactly once, so a fixed nonce is safe
+        let wrap_key = derive_wrap_key(shared_secret.as_bytes(), &ephemeral_public, recipient);
+        let stanza = ChaCha20Poly1305::new(Key::from_slice(&wrap_key))
+            .encrypt(Nonce::from_slice(&[0; NONCE_LENGTH]), payload_key.as_slice())
+            .map_err(|_| "Encryption failed")?;
+        header.extend_from_slice(&stanza);
+    }
+    header.extend_from_slice(&nonce);
+
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&payload_key))
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad: &header })
+        .map_err(|_| "Encryption failed")?;
+
+    Ok([header, ciphertext].concat())
+}
+
+// Decrypt data produced by encrypt_to_recipients by trying identity against each recipient stanza
+pub fn decrypt_with_identity(data: &[u8], identity: &Identity) -> Result<Vec<u8>> {
+    check_header(data, SCHEME_RECIPIENTS)?;
+
+    let ephemeral_start = MAGIC.len() + 2;
+    let stanzas_start = ephemeral_start + KEY_LENGTH + 1;
+    if data.len() < stanzas_start {
+        return Err(Error::InvalidData("Encrypted data is truncated".to_string()));
+    }
+
+    let ephemeral_bytes: [u8; KEY_LENGTH] = data[ephemeral_start..ephemeral_start + KEY_LENGTH].try_into().unwrap();
+    let ephemeral_public = PublicKey::from(ephemeral_bytes);
+    let count = data[stanzas_start - 1] as usize;
+
+    let header_length = stanzas_start + count * STANZA_LENGTH + NONCE_LENGTH;
+    if data.len() < header_length + TAG_LENGTH {
+        return Err(Error::InvalidData("Encrypted data is truncated".to_string()));
+    }
+    let (header, ciphertext) = data.split_at(header_length);
+    let nonce = &header[header_length - NONCE_LENGTH..];
+
+    let shared_secret = identity.secret().diffie_hellman(&ephemeral_public);
+    let wrap_key = derive_wrap_key(shared_secret.as_bytes(), &ephemeral_public, &identity.recipient());
+    let unwrapper = ChaCha20Poly1305::new(Key::from_slice(&wrap_key));
+
+    let payload_key = header[stanzas_start..stanzas_start + count * STANZA_LENGTH]
+        .chunks(STANZA_LENGTH)
+        .find_map(|stanza| unwrapper.decrypt(Nonce::from_slice(&[0; NONCE_LENGTH]), stanza).ok())
+        .ok_or("Decryption failed: identity is not one of the recipients")?;
+
+    ChaCha20Poly1305::new(Key::from_slice(&payload_key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| "Decryption failed: the data has been tampered with".into())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // cheap parameters so tests run quickly
+    fn testing_params() -> KdfParams {
+        KdfParams { memory_cost: 64, time_cost: 1, parallelism: 1 }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let message = b"This is where your secret message will be!";
+        let encrypted = encrypt_with_password_params(message, "hunter2", testing_params()).unwrap();
+        let decrypted = decrypt_with_password(&encrypted, "hunter2").unwrap();
+        assert_eq!(decrypted, message);
+        assert_eq!(encrypted.len(), message.len() + password_overhead());
+    }
+
+    #[test]
+    fn test_scatter_key_is_stable() {
+        // the key orders the pixels of existing images, so it must not follow changes to the argon2 defaults
+        let key = derive_scatter_key("hunter2").unwrap();
+        assert_eq!(key[..8], [205, 244, 104, 221, 40, 67, 62, 211]);
+    }
+
+    #[test]
+    fn test_ciphertext_hides_plaintext() {
+        let message = b"This is where your secret message will be!";
+        let encrypted = encrypt_with_password_params(message, "hunter2", testing_params()).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!encrypted.windows(message.len()).any(|window| window == message));
+    }
+
+    #[test]
+    fn test_header_stores_params() {
+        let encrypted = encrypt_with_password_params(b"Message", "hunter2", testing_params()).unwrap();
+        let header = PasswordHeader::from_bytes(&encrypted).unwrap();
+        assert_eq!(scheme(&encrypted), Some(SCHEME_PASSWORD));
+        assert_eq!(header.params, testing_params());
+        assert_eq!(encrypted.len(), PASSWORD_HEADER_LENGTH + 7 + 16);
+    }
+
+    #[test]
+    fn test_oversized_params() {
+        let oversized = [
+            KdfParams { memory_cost: MAX_MEMORY_COST + 1, ..testing_params() },
+            KdfParams { time_cost: u32::MAX, ..testing_params() },
+            KdfParams { parallelism: MAX_PARALLELISM + 1, ..testing_params() }
+        ];
+        for params in oversized {
+            let header = PasswordHeader { params, salt: [0; SALT_LENGTH], nonce: [0; NONCE_LENGTH] };
+            let data = [header.as_bytes(), vec![0; TAG_LENGTH]].concat();
+            assert!(matches!(PasswordHeader::from_bytes(&data), Err(Error::InvalidData(_))));
+            assert!(matches!(decrypt_with_password(&data, "hunter2"), Err(Error::InvalidData(_))));
+        }
+    }
+
+    #[test]
+    fn test_wrong_password() {
+        let encrypted = encrypt_with_password_params(b"Message", "hunter2", testing_params()).unwrap();
+        assert!(decrypt_with_password(&encrypted, "hunter3").is_err());
+    }
+
+    #[test]
+    fn test_tampered_ciphertext() {
+        let mut encrypted = encrypt_with_password_params(b"Message", "hunter2", testing_params()).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt_with_password(&encrypted, "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_tampered_header() {
+        let mut encrypted = encrypt_with_password_params(b"Message", "hunter2", testing_params()).unwrap();
+        encrypted[20] ^= 1;
+        assert!(decrypt_with_password(&encrypted, "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut encrypted = encrypt_with_password_params(b"Message", "hunter2", testing_params()).unwrap();
+        encrypted[4] = 99;
+        assert!(decrypt_with_password(&encrypted, "hunter2").is_err());
+    }
+
+    #[test]
+    fn test_recipients_roundtrip() {
+        let alice = Identity::generate();
+        let bob = Identity::generate();
+        let message = b"This is where your secret message will be!";
+        let encrypted = encrypt_to_recipients(message, &[alice.recipient(), bob.recipient()]).unwrap();
+
+        assert_eq!(scheme(&encrypted), Some(SCHEME_RECIPIENTS));
+        assert_eq!(encrypted.len(), message.len() + recipients_overhead(2));
+        assert_eq!(decrypt_with_identity(&encrypted, &alice).unwrap(), message);
+        assert_eq!(decrypt_with_identity(&encrypted, &bob).unwrap(), message);
+    }
+
+    #[test]
+    fn test_non_recipient() {
+        let alice = Identity::generate();
+        let eve = Identity::generate();
+        let encrypted = encrypt_to_recipients(b"Message", &[alice.recipient()]).unwrap();
+        assert!(decrypt_with_identity(&encrypted, &eve).is_err());
+    }
+
+    #[test]
+    fn test_recipients_tampered() {
+        let alice = Identity::generate();
+        let mut encrypted = encrypt_to_recipients(b"Message", &[alice.recipient()]).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        assert!(decrypt_with_identity(&encrypted, &alice).is_err());
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        assert!(encrypt_to_recipients(b"Message", &[]).is_err());
+    }
+
+    #[test]
+    fn test_not_encrypted() {
+        assert!(!is_encrypted(b"Plain message"));
+        assert!(decrypt_with_password(b"Plain message", "hunter2").is_err());
+    }
+}
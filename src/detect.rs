@@ -0,0 +1,521 @@
+use std::fmt;
+
+use crate::{Error, Result};
+use crate::disguise;
+use crate::envelope;
+use crate::fragment;
+use crate::pixels::{ColourType, PixelBuffer};
+use crate::png::Png;
+use crate::signature;
+use crate::text::{self, TextChunk};
+use crate::trailer;
+
+const CHI_SQUARE_STEPS: usize = 10;             // prefixes of the image tested for sequential embedding, in tenths
+const CHI_SQUARE_MIN_EXPECTED: f64 = 5.0;       // value pairs expected to occur fewer times are left out of the test
+const CHI_SQUARE_EVEN_P: f64 = 0.5;             // probabilities from this one up are reported as evened out pairs
+const RS_MASK: [bool; 4] = [false, true, true, false];
+const RS_CLEAN_RATE: f64 = 0.05;                // embedding rates RS analysis estimates for untouched images stay below this
+const RS_CERTAIN_RATE: f64 = 0.3;
+const RS_RANDOM_RATIO: f64 = 0.1;               // regular & singular groups this close under the mask mean fully random low bits
+const ENTROPY_MIN_BYTES: usize = 256;
+const ENTROPY_MAX_SCORE: f64 = 0.5;             // noisy photographs have random looking low bits too
+const TEXT_LIMIT: usize = 4096;                 // text entries longer than this are unusual, except XMP packets
+const XMP_TEXT_LIMIT: usize = 65536;
+const UNKNOWN_CHUNK_SCORE: f64 = 0.5;
+const OVERSIZED_TEXT_SCORE: f64 = 0.6;
+const TRAILER_SCORE: f64 = 0.8;
+
+// Result of a single steganalysis test
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evidence {
+    pub test: String,
+    pub score: Option<f64>,     // likelihood of hidden data from 0 to 1, None if the test could not run
+    pub detail: String
+}
+
+impl Evidence {
+    fn new(test: &str, score: f64, detail: String) -> Evidence {
+        Evidence { test: test.to_string(), score: Some(score.clamp(0.0, 1.0)), detail }
+    }
+
+    fn unavailable(test: &str, detail: String) -> Evidence {
+        Evidence { test: test.to_string(), score: None, detail }
+    }
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.score {
+            Some(score) => write!(f, "{:<14}{:>4.0}%  {}", self.test, score * 100.0, self.detail),
+            None => write!(f, "{:<14}{:>5}  {}", self.test, "n/a", self.detail)
+        }
+    }
+}
+
+// Evidence of every test run on an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub evidence: Vec<Evidence>
+}
+
+impl Report {
+    // Overall suspicion, as strong as the strongest evidence
+    pub fn score(&self) -> f64 {
+        self.evidence.iter().filter_map(|evidence| evidence.score).fold(0.0, f64::max)
+    }
+
+    // Word describing the overall suspicion
+    pub fn level(&self) -> &'static str {
+        match self.score() {
+            score if score < 0.3 => "low",
+            score if score < 0.7 => "medium",
+            _ => "high"
+        }
+    }
+}
+
+// Look for signs of hidden data in the pixels & structure of PNG
+pub fn detect(png: &Png) -> Report {
+    let mut evidence = match analysable_pixels(png) {
+        Ok(pixels) => {
+            let (colour, alpha) = channel_samples(&pixels);
+            vec![chi_square_evidence(&colour, &alpha), rs_evidence(&pixels), entropy_evidence(&colour)]
+        },
+        Err(e) => ["chi-square", "rs", "lsb-entropy"].iter().map(|test| Evidence::unavailable(test, e.to_string())).collect()
+    };
+    evidence.extend(structure_evidence(png));
+    Report { evidence }
+}
+
+// Decode pixels of PNG, making sure their low bits can be analysed
+fn analysable_pixels(png: &Png) -> Result<PixelBuffer> {
+    let ihdr = png.ihdr()?;
+    if ihdr.colour_type() == ColourType::Indexed || ihdr.bit_depth() < 8 {
+        return Err(Error::InvalidData("Pixel analysis requires an 8 or 16-bit greyscale or truecolour image".to_string()));
+    }
+    png.pixels()
+}
+
+// Low bytes of the colour & alpha samples, in the order sequential LSB embedding visits them
+fn channel_samples(pixels: &PixelBuffer) -> (Vec<u8>, Vec<u8>) {
+    let channel_count = pixels.colour_type().channel_count();
+    let has_alpha = pixels.colour_type().has_alpha();
+    let (mut colour, mut alpha) = (Vec::new(), Vec::new());
+    for index in 0..pixels.len() {
+        let value = pixels.sample(index) as u8;
+        if has_alpha && index % channel_count == channel_count - 1 {
+            alpha.push(value);
+        } else {
+            colour.push(value);
+        }
+    }
+    (colour, alpha)
+}
+
+// Chi-square attack of Westfeld & Pfitzmann: replacing low bits evens out the counts of values differing only in their lowest bit
+fn chi_square_evidence(colour: &[u8], alpha: &[u8]) -> Evidence {
+    let mut best: Option<(f64, usize, &str)> = None;
+    for (samples, name) in [(colour, "colour"), (alpha, "alpha")] {
+        // embedding from the top of the image shows in the first part of it even when the rest is untouched
+        for step in 1..=CHI_SQUARE_STEPS {
+            let prefix = &samples[..samples.len() * step / CHI_SQUARE_STEPS];
+            if let Some(p) = chi_square_p(prefix) {
+                if best.is_none_or(|(best_p, _, _)| p > best_p) {
+                    best = Some((p, step, name));
+                }
+            }
+        }
+    }
+
+    match best {
+        Some((p, step, name)) if p >= CHI_SQUARE_EVEN_P => Evidence::new(
+            "chi-square",
+            p,
+            format!("{} value pairs are evened out with p = {:.3} over the first {}% of samples", name, p, step * 100 / CHI_SQUARE_STEPS)
+        ),
+        Some((p, _, _)) => Evidence::new("chi-square", p, format!("value pairs are uneven like in untouched images, p = {:.3} at most", p)),
+        None => Evidence::unavailable("chi-square", "Too few samples to compare value pairs".to_string())
+    }
+}
+
+// Probability that the value pairs of samples are as even as embedding random bits leaves them
+fn chi_square_p(samples: &[u8]) -> Option<f64> {
+    let mut histogram = [0u64; 256];
+    for &value in samples {
+        histogram[value as usize] += 1;
+    }
+
+    let (mut chi_square, mut categories) = (0.0, 0);
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected >= CHI_SQUARE_MIN_EXPECTED {
+            chi_square += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories == 0 {
+        return None;
+    }
+
+    let degrees_of_freedom = (categories - 1).max(1) as f64;
+    Some(gamma_q(degrees_of_freedom / 2.0, chi_square / 2.0))
+}
+
+// RS analysis of Fridrich, Goljan & Du: flipping low bits of groups of samples makes them more or less noisy, in proportions embedding shifts
+fn rs_evidence(pixels: &PixelBuffer) -> Evidence {
+    let channel_count = pixels.colour_type().channel_count();
+    let colour_channels = if pixels.colour_type().has_alpha() { channel_count - 1 } else { channel_count };
+
+    let estimates: Vec<Option<f64>> = (0..colour_channels).map(|channel| rs_rate(&channel_groups(pixels, channel))).collect();
+    let known: Vec<f64> = estimates.iter().flatten().copied().collect();
+    if known.is_empty() {
+        return Evidence::unavailable("rs", "Image is too small or too flat to estimate an embedding rate".to_string());
+    }
+
+    let rate = known.iter().copied().fold(0.0, f64::max);
+    let rates: Vec<String> = estimates
+        .iter()
+        .map(|estimate| estimate.map_or("n/a".to_string(), |rate| format!("{:.0}%", rate * 100.0)))
+        .collect();
+    Evidence::new(
+        "rs",
+        (rate - RS_CLEAN_RATE) / (RS_CERTAIN_RATE - RS_CLEAN_RATE),
+        format!("estimated {:.0}% of samples carry hidden bits (per channel {})", rate * 100.0, rates.join(", "))
+    )
+}
+
+// Groups of horizontally adjacent samples of a channel, as many as fit in each row
+fn channel_groups(pixels: &PixelBuffer, channel: usize) -> Vec<[i32; 4]> {
+    let width = pixels.width() as usize;
+    let channel_count = pixels.colour_type().channel_count();
+    let mut groups = Vec::new();
+    for y in 0..pixels.height() as usize {
+        for x in (0..width - width % RS_MASK.len()).step_by(RS_MASK.len()) {
+            let sample = |offset: usize| pixels.sample(((y * width) + x + offset) * channel_count + channel) as i32;
+            groups.push([sample(0), sample(1), sample(2), sample(3)]);
+        }
+    }
+    groups
+}
+
+// Estimated fraction of samples carrying hidden bits, None if the groups do not give an answer
+fn rs_rate(groups: &[[i32; 4]]) -> Option<f64> {
+    if groups.is_empty() {
+        return None;
+    }
+    let flipped: Vec<[i32; 4]> = groups.iter().map(|group| group.map(|value| value ^ 1)).collect();
+
+    // difference between the fractions of regular & singular groups under the mask & the negative mask
+    let d0 = rs_difference(groups, flip);
+    let d1 = rs_difference(&flipped, flip);
+    let n0 = rs_difference(groups, negative_flip);
+    let n1 = rs_difference(&flipped, negative_flip);
+
+    // the differences are a quadratic in the rate with its root where untouched images would be
+    let a = 2.0 * (d1 + d0);
+    let b = n0 - n1 - d1 - 3.0 * d0;
+    let c = d0 - n0;
+    let discriminant = b * b - 4.0 * a * c;
+    let z = if a.abs() < f64::EPSILON && b.abs() > f64::EPSILON {
+        Some(-c / b)
+    } else if a.abs() >= f64::EPSILON && discriminant >= 0.0 {
+        let roots = [(-b + discriminant.sqrt()) / (2.0 * a), (-b - discriminant.sqrt()) / (2.0 * a)];
+        Some(if roots[0].abs() < roots[1].abs() { roots[0] } else { roots[1] })
+    } else {
+        None
+    };
+
+    match z.map(|z| z / (z - 0.5)).filter(|rate| rate.is_finite()) {
+        Some(rate) => Some(rate.clamp(0.0, 1.0)),
+        // with every low bit random, regular & singular groups balance out under the mask & the quadratic has no root
+        None if d0.abs() < RS_RANDOM_RATIO * n0.abs() => Some(1.0),
+        None => None
+    }
+}
+
+// Fraction of regular groups less the fraction of singular groups once the masked samples are flipped
+fn rs_difference(groups: &[[i32; 4]], flip: fn(i32) -> i32) -> f64 {
+    let mut difference = 0i64;
+    for group in groups {
+        let mut flipped = *group;
+        for (value, masked) in flipped.iter_mut().zip(RS_MASK) {
+            if masked {
+                *value = flip(*value);
+            }
+        }
+        difference += match noise(&flipped).cmp(&noise(group)) {
+            std::cmp::Ordering::Greater => 1,
+            std::cmp::Ordering::Less => -1,
+            std::cmp::Ordering::Equal => 0
+        };
+    }
+    difference as f64 / groups.len() as f64
+}
+
+// Swap values differing only in their lowest bit, 2 with 3
+fn flip(value: i32) -> i32 {
+    value ^ 1
+}
+
+// Swap values on the other side of each pair, 1 with 2
+fn negative_flip(value: i32) -> i32 {
+    ((value + 1) ^ 1) - 1
+}
+
+// Smoothness of a group, the total change between neighbouring samples
+fn noise(group: &[i32; 4]) -> i32 {
+    group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum()
+}
+
+// Entropy of the lowest bit plane read as bytes, which encrypted data makes as random as it gets
+fn entropy_evidence(colour: &[u8]) -> Evidence {
+    let bytes: Vec<u8> = colour
+        .chunks_exact(8)
+        .map(|samples| samples.iter().fold(0, |byte, sample| (byte << 1) | (sample & 1)))
+        .collect();
+    if bytes.len() < ENTROPY_MIN_BYTES {
+        return Evidence::unavailable("lsb-entropy", "Too few samples to measure the entropy of the lowest bits".to_string());
+    }
+
+    let entropy = byte_entropy(&bytes);
+    Evidence::new(
+        "lsb-entropy",
+        ((entropy - 7.0) * ENTROPY_MAX_SCORE).min(ENTROPY_MAX_SCORE),
+        format!("lowest bits hold {:.3} bits of entropy per byte, 8 is random", entropy)
+    )
+}
+
+// Shannon entropy of bytes in bits, with the Miller-Madow correction for the bias of small samples
+fn byte_entropy(bytes: &[u8]) -> f64 {
+    let mut histogram = [0usize; 256];
+    for &byte in bytes {
+        histogram[byte as usize] += 1;
+    }
+    let total = bytes.len() as f64;
+    let observed = histogram.iter().filter(|&&count| count > 0).count() as f64;
+    let entropy: f64 = histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let probability = count as f64 / total;
+            -probability * probability.log2()
+        })
+        .sum();
+    (entropy + (observed - 1.0) / (2.0 * total * std::f64::consts::LN_2)).min(8.0)
+}
+
+// Chunks, text & trailing data that may hold hidden data
+fn structure_evidence(png: &Png) -> Vec<Evidence> {
+    let mut evidence = Vec::new();
+
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.is_registered() {
+            continue;
+        }
+        let data = chunk.data();
+        if envelope::holds_envelope(data) || fragment::is_fragment(data) {
+            evidence.push(Evidence::new("chunk", 1.0, format!("chunk {} {} holds imgcrypt data, {} bytes", index, chunk_type, data.len())));
+        } else if chunk_type.to_string() == signature::SIGNATURE_CHUNK_TYPE {
+            evidence.push(Evidence::new("chunk", 1.0, format!("chunk {} {} is an imgcrypt signature", index, chunk_type)));
+        } else {
+            let kind = if chunk_type.is_public() { "unregistered public" } else { "private" };
+            evidence.push(Evidence::new("chunk", UNKNOWN_CHUNK_SCORE, format!("chunk {} {} is {}, {} bytes", index, chunk_type, kind, data.len())));
+        }
+    }
+
+    if disguise::reveal(png).is_some() {
+        evidence.push(Evidence::new("text", 1.0, "Comment or XMP metadata holds imgcrypt data".to_string()));
+    }
+    for (index, chunk) in png.chunks().iter().enumerate().filter(|(_, chunk)| text::is_text_chunk(chunk)) {
+        let Ok(text_chunk) = TextChunk::try_from(chunk) else {
+            continue;
+        };
+        let limit = if text_chunk.keyword() == disguise::XMP_KEYWORD { XMP_TEXT_LIMIT } else { TEXT_LIMIT };
+        if text_chunk.text().len() > limit {
+            evidence.push(Evidence::new(
+                "text",
+                OVERSIZED_TEXT_SCORE,
+                format!("chunk {} {} \"{}\" holds {} bytes of text", index, chunk.chunk_type(), text_chunk.keyword(), text_chunk.text().len())
+            ));
+        }
+    }
+
+    let trailer_length = png.trailer().len();
+    if trailer::reveal(png).is_some() {
+        evidence.push(Evidence::new("trailer", 1.0, format!("{} bytes after IEND hold imgcrypt data", trailer_length)));
+    } else if trailer_length > 0 {
+        evidence.push(Evidence::new("trailer", TRAILER_SCORE, format!("{} bytes of trailing data after IEND", trailer_length)));
+    }
+
+    if evidence.is_empty() {
+        evidence.push(Evidence::new("structure", 0.0, "no unknown chunks, oversized text or trailing data".to_string()));
+    }
+    evidence
+}
+
+// Regularized upper incomplete gamma function Q(a, x), the survival function of the chi-square distribution
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    if x < a + 1.0 {
+        // series for the lower function
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        while term.abs() > sum.abs() * 1e-15 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+        }
+        1.0 - sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+    } else {
+        // continued fraction for the upper function, evaluated with the modified Lentz method
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut fraction = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            d = if d.abs() < tiny { 1.0 / tiny } else { 1.0 / d };
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            let delta = d * c;
+            fraction *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        fraction * (-x + a * x.ln() - ln_gamma(a)).exp()
+    }
+}
+
+// Natural logarithm of the gamma function, using the Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9, 676.520_368_121_885_1, -1_259.139_216_722_402_8, 771.323_428_777_653_1,
+        -176.615_029_162_140_6, 12.507_343_278_686_905, -0.138_571_095_265_720_12, 9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7
+    ];
+    if x < 0.5 {
+        // reflection formula
+        return (std::f64::consts::PI / (std::f64::consts::PI * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFICIENTS[1..].iter().enumerate().fold(COEFFICIENTS[0], |sum, (i, c)| sum + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::{Ihdr, InterlaceMethod};
+    use crate::lsb::{self, LsbOptions};
+
+    // Smooth greyscale image with mild noise, like a photograph, made of even values only so its value pairs are uneven
+    fn testing_png() -> Png {
+        let (width, height) = (128, 96);
+        let mut pixels = PixelBuffer::new(width, height, ColourType::Greyscale, 8).unwrap();
+        let mut state = 12345u32;
+        for y in 0..height {
+            for x in 0..width {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                let noise = (state >> 16) % 5;
+                let value = 120.0 + 60.0 * (x as f64 / 11.0).sin() * (y as f64 / 13.0).cos();
+                pixels.set_sample(pixels.index(x, y, 0), (value as u16 + noise as u16) & !1);
+            }
+        }
+
+        let ihdr = Ihdr::new(width, height, 8, ColourType::Greyscale, InterlaceMethod::None).unwrap();
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), Vec::new());
+        let mut png = Png::from_chunks(vec![ihdr.to_chunk(), chunk("IDAT"), chunk("IEND")]);
+        png.set_pixels(&pixels).unwrap();
+        png
+    }
+
+    fn score_of(report: &Report, test: &str) -> f64 {
+        report.evidence.iter().find(|evidence| evidence.test == test).unwrap().score.unwrap()
+    }
+
+    // Bytes that look like encrypted data
+    fn random_bytes(length: usize) -> Vec<u8> {
+        let mut state = 987654321u32;
+        (0..length)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_gamma_q() {
+        // with 2 degrees of freedom the chi-square survival function is exp(-x / 2)
+        for x in [0.1, 1.0, 3.0, 10.0] {
+            assert!((gamma_q(1.0, x) - (-x).exp()).abs() < 1e-10);
+        }
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-10);
+        assert!((gamma_q(63.5, 63.5) - 0.4833).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = detect(&testing_png());
+        assert!(score_of(&report, "chi-square") < 0.01);
+        assert!(score_of(&report, "rs") < 0.01);
+        assert_eq!(score_of(&report, "structure"), 0.0);
+        assert_eq!(report.level(), "low");
+    }
+
+    #[test]
+    fn test_lsb_embedding() {
+        let mut png = testing_png();
+        let capacity = lsb::capacity(&png, &LsbOptions::default()).unwrap();
+        lsb::embed(&mut png, &random_bytes(capacity), &LsbOptions::default()).unwrap();
+
+        let report = detect(&png);
+        assert!(score_of(&report, "chi-square") > 0.5);
+        assert_eq!(score_of(&report, "rs"), 1.0);
+        assert!(score_of(&report, "lsb-entropy") > 0.4);
+        assert_eq!(report.level(), "high");
+
+        // sequential embedding in a fifth of the image still shows at its top
+        let mut png = testing_png();
+        lsb::embed(&mut png, &random_bytes(capacity / 5), &LsbOptions::default()).unwrap();
+        assert!(score_of(&detect(&png), "chi-square") > 0.5);
+
+        // bits scattered over the whole image are counted by RS analysis
+        let mut png = testing_png();
+        let options = LsbOptions { scatter_key: Some([7; 32]), ..LsbOptions::default() };
+        lsb::embed(&mut png, &random_bytes(capacity / 2), &options).unwrap();
+        let rate = rs_rate(&channel_groups(&png.pixels().unwrap(), 0)).unwrap();
+        assert!((rate - 0.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_structure() {
+        let mut png = testing_png();
+        png.insert_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Unknown".to_vec()));
+        png.set_trailer(b"Leftover".to_vec());
+
+        let report = detect(&png);
+        assert_eq!(score_of(&report, "chunk"), UNKNOWN_CHUNK_SCORE);
+        assert_eq!(score_of(&report, "trailer"), TRAILER_SCORE);
+        assert!(report.evidence.iter().all(|evidence| evidence.test != "structure"));
+
+        let comment = TextChunk::Text { keyword: "Comment".to_string(), text: "a".repeat(TEXT_LIMIT + 1) };
+        png.insert_chunk(comment.to_chunk().unwrap());
+        assert_eq!(score_of(&detect(&png), "text"), OVERSIZED_TEXT_SCORE);
+    }
+}
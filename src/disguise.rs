@@ -0,0 +1,286 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::envelope;
+use crate::png::Png;
+use crate::text::{self, TextChunk};
+
+pub const COMMENT_KEYWORD: &str = "Comment";
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+const LINE_LENGTH: usize = 76;          // base64 line length used by MIME & XMP thumbnails
+const THUMBNAIL_SIZE: u32 = 256;        // longest side of the thumbnail advertised in XMP
+const XMP_LINE_BREAK: &str = "&#xA;";   // line break escaped inside XMP element text
+const IMAGE_START_TAG: &str = "<xmpGImg:image>";
+const IMAGE_END_TAG: &str = "</xmpGImg:image>";
+
+// Standard text entry hiding data in plain sight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEntry {
+    // tEXt Comment holding base64 lines, as left behind by tools dumping binary metadata
+    Comment,
+
+    // iTXt XMP packet with the data posing as the JPEG thumbnail image editors embed
+    Xmp
+}
+
+impl TextEntry {
+    // Keyword of the text chunk holding the entry
+    pub fn keyword(self) -> &'static str {
+        match self {
+            TextEntry::Comment => COMMENT_KEYWORD,
+            TextEntry::Xmp => XMP_KEYWORD
+        }
+    }
+
+    // Wrap data in text looking like an ordinary entry of this kind
+    pub fn wrap(self, data: &[u8], png: &Png) -> TextChunk {
+        match self {
+            TextEntry::Comment => TextChunk::Text {
+                keyword: COMMENT_KEYWORD.to_string(),
+                text: wrap_base64(data, "\n")
+            },
+            TextEntry::Xmp => TextChunk::InternationalText {
+                keyword: XMP_KEYWORD.to_string(),
+                compressed: false,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+                text: xmp_packet(data, png)
+            }
+        }
+    }
+
+    // Largest data that fits in a single chunk of this entry
+    pub fn capacity(self, png: &Png) -> usize {
+        // binary search, the chunk grows with data
+        let (mut low, mut high) = (0, Chunk::MAX_LENGTH as usize);
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            if self.chunk_length(middle, png) <= Chunk::MAX_LENGTH as usize {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        low
+    }
+
+    // Length of the chunk data holding data_length bytes, as written by wrap
+    fn chunk_length(self, data_length: usize, png: &Png) -> usize {
+        let encoded = data_length.div_ceil(3) * 4;
+        let line_breaks = encoded.div_ceil(LINE_LENGTH).saturating_sub(1);
+        let keyword = self.keyword().len() + 1;
+        match self {
+            TextEntry::Comment => keyword + encoded + line_breaks,
+            // compression flag & method, empty language tag & translated keyword
+            TextEntry::Xmp => keyword + 4 + xmp_packet(&[], png).len() + encoded + line_breaks * XMP_LINE_BREAK.len()
+        }
+    }
+
+    // Recover data from the text of an entry, None if it does not hold an imgcrypt envelope
+    pub fn extract(self, text: &str) -> Option<Vec<u8>> {
+        let encoded = match self {
+            TextEntry::Comment => text,
+            TextEntry::Xmp => {
+                let start = text.find(IMAGE_START_TAG)? + IMAGE_START_TAG.len();
+                let end = start + text[start..].find(IMAGE_END_TAG)?;
+                &text[start..end]
+            }
+        };
+        let encoded: String = encoded.replace(XMP_LINE_BREAK, "").split_whitespace().collect();
+        BASE64.decode(encoded).ok().filter(|data| envelope::holds_envelope(data))
+    }
+}
+
+// Hide data in a text entry placed before the image data, where editors write their metadata
+pub fn hide(png: &mut Png, data: &[u8], entry: TextEntry) -> Result<()> {
+    if entry == TextEntry::Xmp && text_entries(png).any(|text_chunk| text_chunk.keyword() == XMP_KEYWORD) {
+        return Err(Error::Usage("PNG already holds XMP metadata, hide the message in a comment instead".to_string()));
+    }
+    png.insert_before("IDAT", entry.wrap(data, png).to_chunk()?)
+}
+
+// Find data hidden by hide in any comment or XMP entry
+pub fn reveal(png: &Png) -> Option<Vec<u8>> {
+    text_entries(png)
+        .find_map(|text_chunk| {
+            [TextEntry::Comment, TextEntry::Xmp]
+                .iter()
+                .filter(|entry| entry.keyword() == text_chunk.keyword())
+                .find_map(|entry| entry.extract(text_chunk.text()))
+        })
+}
+
+// Check if chunk data of the given type is an entry written by hide, judging by the envelope at the start of its base64 text
+// so the chunk is still recognised when damage further in broke its CRC
+pub fn is_carrier(chunk_type: &str, data: &[u8]) -> bool {
+    let encoded = match chunk_type {
+        "tEXt" => data.strip_prefix(format!("{}\0", COMMENT_KEYWORD).as_bytes()),
+        "iTXt" if data.starts_with(format!("{}\0", XMP_KEYWORD).as_bytes()) => data
+            .windows(IMAGE_START_TAG.len())
+            .position(|window| window == IMAGE_START_TAG.as_bytes())
+            .map(|start| &data[start + IMAGE_START_TAG.len()..]),
+        _ => None
+    };
+
+    // the first line holds enough whole base64 groups for an envelope or error correction header
+    encoded.is_some_and(|encoded| {
+        let line = &encoded[..encoded.len().min(LINE_LENGTH)];
+        let valid = line.iter().take_while(|&&byte| byte.is_ascii_alphanumeric() || byte == b'+' || byte == b'/').count();
+        BASE64.decode(&line[..valid / 4 * 4]).is_ok_and(|data| envelope::holds_envelope(&data))
+    })
+}
+
+// Text chunks that parse, skipping malformed ones
+fn text_entries(png: &Png) -> impl Iterator<Item = TextChunk> + '_ {
+    png.chunks()
+        .iter()
+        .filter(|chunk| text::is_text_chunk(chunk))
+        .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+}
+
+// Base64 encode data into lines of LINE_LENGTH characters joined by separator
+fn wrap_base64(data: &[u8], separator: &str) -> String {
+    let encoded = BASE64.encode(data);
+    encoded
+        .as_bytes()
+        .chunks(LINE_LENGTH)
+        .map(|line| std::str::from_utf8(line).unwrap_or_default())
+        .collect::<Vec<&str>>()
+        .join(separator)
+}
+
+// XMP packet laid out the way Photoshop writes it, with data as the thumbnail image
+fn xmp_packet(data: &[u8], png: &Png) -> String {
+    let (width, height) = png.ihdr().map_or((THUMBNAIL_SIZE, THUMBNAIL_SIZE), |ihdr| (ihdr.width().max(1), ihdr.height().max(1)));
+    // thumbnails are scaled down to fit THUMBNAIL_SIZE, never up
+    let longest = width.max(height).max(THUMBNAIL_SIZE) as u64;
+    let thumbnail_width = (width as u64 * THUMBNAIL_SIZE as u64 / longest).max(1);
+    let thumbnail_height = (height as u64 * THUMBNAIL_SIZE as u64 / longest).max(1);
+
+    let mut id = [0; 16];
+    OsRng.fill_bytes(&mut id);
+    let id = hex::encode(id);
+    let document_id = format!("{}-{}-{}-{}-{}", &id[..8], &id[8..12], &id[12..16], &id[16..20], &id[20..]);
+
+    format!(
+r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/" x:xmptk="Adobe XMP Core 9.1-c001 79.a8d4753, 2023/03/23-08:56:37        ">
+   <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+      <rdf:Description rdf:about=""
+            xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+            xmlns:xmpGImg="http://ns.adobe.com/xap/1.0/g/img/"
+            xmlns:xmpMM="http://ns.adobe.com/xap/1.0/mm/"
+            xmlns:tiff="http://ns.adobe.com/tiff/1.0/">
+         <xmp:CreatorTool>Adobe Photoshop 25.0 (Windows)</xmp:CreatorTool>
+         <xmpMM:DocumentID>xmp.did:{document_id}</xmpMM:DocumentID>
+         <xmpMM:InstanceID>xmp.iid:{document_id}</xmpMM:InstanceID>
+         <tiff:ImageWidth>{width}</tiff:ImageWidth>
+         <tiff:ImageLength>{height}</tiff:ImageLength>
+         <xmp:Thumbnails>
+            <rdf:Alt>
+               <rdf:li rdf:parseType="Resource">
+                  <xmpGImg:format>JPEG</xmpGImg:format>
+                  <xmpGImg:width>{thumbnail_width}</xmpGImg:width>
+                  <xmpGImg:height>{thumbnail_height}</xmpGImg:height>
+                  {IMAGE_START_TAG}{image}{IMAGE_END_TAG}
+               </rdf:li>
+            </rdf:Alt>
+         </xmp:Thumbnails>
+      </rdf:Description>
+   </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        bom = '\u{feff}',
+        image = wrap_base64(data, XMP_LINE_BREAK)
+    )
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::Algorithm;
+    use crate::envelope::Envelope;
+    use crate::test_support::placeholder_png;
+
+    fn testing_png() -> Png {
+        placeholder_png(&["IHDR", "IDAT", "IEND"])
+    }
+
+    fn testing_data() -> Vec<u8> {
+        Envelope::seal(0, Algorithm::None, b"Secret", b"Secret ".repeat(40)).as_bytes()
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_comment_roundtrip() {
+        let mut png = testing_png();
+        hide(&mut png, &testing_data(), TextEntry::Comment).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+
+        let text_chunk = TextChunk::try_from(&png.chunks()[1]).unwrap();
+        assert_eq!(text_chunk.keyword(), "Comment");
+        assert!(text_chunk.text().lines().all(|line| line.len() <= LINE_LENGTH));
+        assert_eq!(reveal(&png).unwrap(), testing_data());
+    }
+
+    #[test]
+    fn test_xmp_roundtrip() {
+        let mut png = testing_png();
+        hide(&mut png, &testing_data(), TextEntry::Xmp).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "iTXt", "IDAT", "IEND"]);
+
+        let text_chunk = TextChunk::try_from(&png.chunks()[1]).unwrap();
+        assert_eq!(text_chunk.keyword(), XMP_KEYWORD);
+        assert!(text_chunk.text().starts_with("<?xpacket begin="));
+        assert!(text_chunk.text().contains("<xmpGImg:format>JPEG</xmpGImg:format>"));
+        assert_eq!(reveal(&png).unwrap(), testing_data());
+
+        // a second packet would be invalid XMP
+        assert!(hide(&mut png, &testing_data(), TextEntry::Xmp).is_err());
+    }
+
+    #[test]
+    fn test_chunk_length() {
+        let png = testing_png();
+        for entry in [TextEntry::Comment, TextEntry::Xmp] {
+            for length in [0, 1, 56, 57, 58, 500] {
+                let chunk = entry.wrap(&vec![0; length], &png).to_chunk().unwrap();
+                assert_eq!(entry.chunk_length(length, &png), chunk.data().len());
+            }
+            assert!(entry.capacity(&png) < Chunk::MAX_LENGTH as usize * 3 / 4);
+            assert!(entry.chunk_length(entry.capacity(&png) + 1, &png) > Chunk::MAX_LENGTH as usize);
+        }
+    }
+
+    #[test]
+    fn test_is_carrier() {
+        let png = testing_png();
+        for entry in [TextEntry::Comment, TextEntry::Xmp] {
+            let chunk = entry.wrap(&testing_data(), &png).to_chunk().unwrap();
+            assert!(is_carrier(&chunk.chunk_type().to_string(), chunk.data()));
+            let chunk = entry.wrap(b"Not an envelope", &png).to_chunk().unwrap();
+            assert!(!is_carrier(&chunk.chunk_type().to_string(), chunk.data()));
+        }
+        assert!(!is_carrier("tEXt", b"Comment\0Created with GIMP"));
+        assert!(!is_carrier("zTXt", b"Comment\0\0"));
+    }
+
+    #[test]
+    fn test_ordinary_metadata_ignored() {
+        let mut png = testing_png();
+        let comment = TextChunk::Text { keyword: "Comment".to_string(), text: "Created with GIMP".to_string() };
+        png.insert_before("IDAT", comment.to_chunk().unwrap()).unwrap();
+        assert!(reveal(&png).is_none());
+
+        hide(&mut png, &testing_data(), TextEntry::Comment).unwrap();
+        assert_eq!(reveal(&png).unwrap(), testing_data());
+    }
+}
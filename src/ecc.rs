@@ -0,0 +1,378 @@
+use crate::{Error, Result};
+
+// Reed-Solomon forward error correction over GF(256), following the "Reed-Solomon codes for coders" construction
+// Protected layout:
+//   0  header: magic "IECC", format version, parity symbols per block & data length (u64), followed by its own parity
+//  30  codewords of the data blocks, interleaved byte by byte so a run of corrupted bytes is spread over every block
+const MAGIC: [u8; 4] = *b"IECC";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_DATA_LENGTH: usize = MAGIC.len() + 1 + 1 + 8;
+const HEADER_PARITY: usize = 16;    // header survives up to 8 corrupted bytes
+const HEADER_LENGTH: usize = HEADER_DATA_LENGTH + HEADER_PARITY;
+pub const CODEWORD_LENGTH: usize = 255;
+const PRIMITIVE_POLYNOMIAL: u16 = 0x11d;
+pub const MAX_PERCENT: u8 = 200;
+
+// Exponential & logarithm tables of GF(256), the exponential table is doubled to skip reducing sums of logarithms
+const TABLES: ([u8; 512], [u8; 256]) = build_tables();
+
+const fn build_tables() -> ([u8; 512], [u8; 256]) {
+    let mut exp = [0; 512];
+    let mut log = [0; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= PRIMITIVE_POLYNOMIAL;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    (exp, log)
+}
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    TABLES.0[TABLES.1[a as usize] as usize + TABLES.1[b as usize] as usize]
+}
+
+fn gf_div(a: u8, b: u8) -> u8 {
+    if a == 0 {
+        return 0;
+    }
+    TABLES.0[(TABLES.1[a as usize] as usize + 255 - TABLES.1[b as usize] as usize) % 255]
+}
+
+fn gf_pow(a: u8, power: i32) -> u8 {
+    TABLES.0[(TABLES.1[a as usize] as i32 * power).rem_euclid(255) as usize]
+}
+
+fn gf_inverse(a: u8) -> u8 {
+    TABLES.0[255 - TABLES.1[a as usize] as usize]
+}
+
+// Polynomials are stored highest degree first
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+    p.iter().map(|&c| gf_mul(c, x)).collect()
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let length = p.len().max(q.len());
+    let mut sum = vec![0; length];
+    for (i, &c) in p.iter().enumerate() {
+        sum[i + length - p.len()] = c;
+    }
+    for (i, &c) in q.iter().enumerate() {
+        sum[i + length - q.len()] ^= c;
+    }
+    sum
+}
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+    let mut product = vec![0; p.len() + q.len() - 1];
+    for (j, &b) in q.iter().enumerate() {
+        for (i, &a) in p.iter().enumerate() {
+            product[i + j] ^= gf_mul(a, b);
+        }
+    }
+    product
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+    p.iter().fold(0, |y, &c| gf_mul(y, x) ^ c)
+}
+
+// Product of (x - 2^i) for every parity symbol
+fn generator(parity: usize) -> Vec<u8> {
+    (0..parity).fold(vec![1], |g, i| poly_mul(&g, &[1, gf_pow(2, i as i32)]))
+}
+
+// Append parity symbols to message, the remainder of dividing it by the generator
+fn encode_block(message: &[u8], parity: usize) -> Vec<u8> {
+    let generator = generator(parity);
+    let mut codeword = [message, &vec![0; parity]].concat();
+    for i in 0..message.len() {
+        let coefficient = codeword[i];
+        if coefficient != 0 {
+            for (j, &g) in generator.iter().enumerate().skip(1) {
+                codeword[i + j] ^= gf_mul(g, coefficient);
+            }
+        }
+    }
+    codeword[..message.len()].copy_from_slice(message);
+    codeword
+}
+
+// Syndromes of codeword with a leading zero, all zero when it is intact
+fn syndromes(codeword: &[u8], parity: usize) -> Vec<u8> {
+    std::iter::once(0).chain((0..parity).map(|i| poly_eval(codeword, gf_pow(2, i as i32)))).collect()
+}
+
+// Error locator polynomial found with the Berlekamp-Massey algorithm
+fn error_locator(syndromes: &[u8], parity: usize) -> Option<Vec<u8>> {
+    let mut locator = vec![1];
+    let mut old_locator = vec![1];
+
+    for i in 0..parity {
+        let k = i + 1;
+        let mut delta = syndromes[k];
+        for j in 1..locator.len() {
+            delta ^= gf_mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+        }
+        old_locator.push(0);
+        if delta != 0 {
+            if old_locator.len() > locator.len() {
+                let new_locator = poly_scale(&old_locator, delta);
+                old_locator = poly_scale(&locator, gf_inverse(delta));
+                locator = new_locator;
+            }
+            locator = poly_add(&locator, &poly_scale(&old_locator, delta));
+        }
+    }
+
+    let start = locator.iter().position(|&c| c != 0).unwrap_or(locator.len());
+    let locator = locator[start..].to_vec();
+    if (locator.len().saturating_sub(1)) * 2 > parity { None } else { Some(locator) }
+}
+
+// Positions of the errors, the roots of the locator found by Chien search
+fn error_positions(locator: &[u8], length: usize) -> Option<Vec<usize>> {
+    let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+    let positions: Vec<usize> = (0..length).filter(|&i| poly_eval(&reversed, gf_pow(2, i as i32)) == 0).map(|i| length - 1 - i).collect();
+    if positions.len() == locator.len() - 1 { Some(positions) } else { None }
+}
+
+// Fix the errors at positions using the Forney algorithm
+fn correct_errata(codeword: &mut [u8], syndromes: &[u8], positions: &[usize]) -> Option<()> {
+    let coefficient_positions: Vec<usize> = positions.iter().map(|&p| codeword.len() - 1 - p).collect();
+    let locator = coefficient_positions
+        .iter()
+        .fold(vec![1], |locator, &i| poly_mul(&locator, &poly_add(&[1], &[gf_pow(2, i as i32), 0])));
+
+    // error evaluator, the syndromes times the locator modulo x^(errors + 1)
+    let reversed_syndromes: Vec<u8> = syndromes.iter().rev().copied().collect();
+    let product = poly_mul(&reversed_syndromes, &locator);
+    let evaluator: Vec<u8> = product[product.len().saturating_sub(locator.len())..].to_vec();
+
+    let roots: Vec<u8> = coefficient_positions.iter().map(|&p| gf_pow(2, -(255 - p as i32))).collect();
+    for (i, &root) in roots.iter().enumerate() {
+        let root_inverse = gf_inverse(root);
+        let locator_derivative = roots
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != i)
+            .fold(1, |product, (_, &other)| gf_mul(product, 1 ^ gf_mul(root_inverse, other)));
+        if locator_derivative == 0 {
+            return None;
+        }
+        let y = gf_mul(root, poly_eval(&evaluator, root_inverse));
+        codeword[positions[i]] ^= gf_div(y, locator_derivative);
+    }
+    Some(())
+}
+
+// Correct codeword in place, returning the number of corrected symbols or None if there are too many errors
+fn decode_block(codeword: &mut [u8], parity: usize) -> Option<usize> {
+    let syndromes = syndromes(codeword, parity);
+    if syndromes.iter().all(|&s| s == 0) {
+        return Some(0);
+    }
+
+    let locator = error_locator(&syndromes, parity)?;
+    let positions = error_positions(&locator, codeword.len())?;
+    correct_errata(codeword, &syndromes, &positions)?;
+
+    if self::syndromes(codeword, parity).iter().any(|&s| s != 0) {
+        return None;
+    }
+    Some(positions.len())
+}
+
+// Parity symbols per 255 byte codeword giving roughly percent parity bytes for every 100 data bytes
+pub fn parity_for_percent(percent: u8) -> Result<usize> {
+    if !(1..=MAX_PERCENT).contains(&percent) {
+        return Err(Error::Usage(format!("Error correction must be between 1 and {} percent", MAX_PERCENT)));
+    }
+    let percent = percent as usize;
+    Ok(((CODEWORD_LENGTH * percent + (100 + percent) / 2) / (100 + percent)).max(2))
+}
+
+// Block geometry for data of length bytes: number of blocks & data bytes in each
+fn blocks(length: usize, parity: usize) -> (usize, usize) {
+    let count = length.div_ceil(CODEWORD_LENGTH - parity).max(1);
+    (count, length.div_ceil(count))
+}
+
+// Length of data once protected by protect
+pub fn protected_length(length: usize, parity: usize) -> usize {
+    let (count, block_length) = blocks(length, parity);
+    HEADER_LENGTH + count * (block_length + parity)
+}
+
+// Add parity to data so up to parity / 2 corrupted bytes in every block can be corrected
+pub fn protect(data: &[u8], parity: usize) -> Vec<u8> {
+    let header: Vec<u8> = MAGIC
+        .iter()
+        .chain([FORMAT_VERSION, parity as u8].iter())
+        .chain((data.len() as u64).to_be_bytes().iter())
+        .copied()
+        .collect();
+
+    let (count, block_length) = blocks(data.len(), parity);
+    let codewords: Vec<Vec<u8>> = (0..count)
+        .map(|block| {
+            let start = (block * block_length).min(data.len());
+            let mut message = data[start..(start + block_length).min(data.len())].to_vec();
+            message.resize(block_length, 0);
+            encode_block(&message, parity)
+        })
+        .collect();
+
+    let mut protected = encode_block(&header, HEADER_PARITY);
+    for position in 0..block_length + parity {
+        protected.extend(codewords.iter().map(|codeword| codeword[position]));
+    }
+    protected
+}
+
+// Read the header of protected data, correcting it if needed, returning parity, data length & corrected symbols
+fn read_header(data: &[u8]) -> Option<(usize, usize, usize)> {
+    let mut header = data.get(..HEADER_LENGTH)?.to_vec();
+    let corrected = decode_block(&mut header, HEADER_PARITY)?;
+    if header[..MAGIC.len()] != MAGIC || header[4] != FORMAT_VERSION || !(2..CODEWORD_LENGTH).contains(&(header[5] as usize)) {
+        return None;
+    }
+    let length = u64::from_be_bytes(header[6..HEADER_DATA_LENGTH].try_into().ok()?);
+    Some((header[5] as usize, usize::try_from(length).ok()?, corrected))
+}
+
+// Check if data was protected by protect, even if its header is damaged
+pub fn is_protected(data: &[u8]) -> bool {
+    read_header(data).is_some()
+}
+
+// Data recovered from protected data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovered {
+    pub data: Vec<u8>,
+    pub corrected: usize    // number of corrupted bytes that were fixed
+}
+
+// Correct & strip the parity added by protect
+pub fn recover(protected: &[u8]) -> Result<Recovered> {
+    let (parity, length, mut corrected) = read_header(protected)
+        .ok_or_else(|| Error::InvalidData("Error correction header is missing or damaged beyond repair".to_string()))?;
+
+    let body = &protected[HEADER_LENGTH..];
+    if length > body.len() {
+        return Err(Error::InvalidData(format!("Error corrected data should hold {} bytes, found {}", length, body.len())));
+    }
+
+    let (count, block_length) = blocks(length, parity);
+    // bytes past the last codeword are ignored, a damaged length may have read too far
+    if body.len() < count * (block_length + parity) {
+        return Err(Error::InvalidData(format!("Error corrected data should be {} bytes long, found {}", count * (block_length + parity), body.len())));
+    }
+
+    let mut data = Vec::with_capacity(count * block_length);
+    for block in 0..count {
+        let mut codeword: Vec<u8> = body.iter().skip(block).step_by(count).copied().collect();
+        corrected += decode_block(&mut codeword, parity)
+            .ok_or_else(|| Error::InvalidData(format!("Block {} of {} has too many corrupted bytes to correct", block + 1, count)))?;
+        data.extend(&codeword[..block_length]);
+    }
+    data.truncate(length);
+
+    Ok(Recovered { data, corrected })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_data() -> Vec<u8> {
+        (0..1000u32).map(|i| (i * 7 % 256) as u8).collect()
+    }
+
+    #[test]
+    fn test_block_roundtrip() {
+        let message = b"hello world".to_vec();
+        let codeword = encode_block(&message, 10);
+        assert_eq!(&codeword[..11], message.as_slice());
+        assert!(syndromes(&codeword, 10).iter().all(|&s| s == 0));
+
+        let mut corrupted = codeword.clone();
+        for position in [0, 3, 7, 15, 20] {
+            corrupted[position] ^= 0x5a;
+        }
+        assert_eq!(decode_block(&mut corrupted, 10), Some(5));
+        assert_eq!(corrupted, codeword);
+
+        // 6 errors are more than 10 parity symbols can correct, so the block is reported as lost and left alone
+        for position in [1, 2, 4, 5, 6, 8] {
+            corrupted[position] ^= 1;
+        }
+        let damaged = corrupted.clone();
+        assert_eq!(decode_block(&mut corrupted, 10), None);
+        assert_eq!(corrupted, damaged);
+    }
+
+    #[test]
+    fn test_protect_recover() {
+        let parity = parity_for_percent(20).unwrap();
+        let protected = protect(&testing_data(), parity);
+        assert_eq!(protected.len(), protected_length(1000, parity));
+        assert!(is_protected(&protected));
+        assert_eq!(recover(&protected).unwrap(), Recovered { data: testing_data(), corrected: 0 });
+
+        assert!(recover(&protected[..protected.len() - 1]).is_err());
+        let extended = [protected, vec![0; 8]].concat();
+        assert_eq!(recover(&extended).unwrap().data, testing_data());
+    }
+
+    #[test]
+    fn test_recover_burst() {
+        let parity = parity_for_percent(20).unwrap();
+        let mut protected = protect(&testing_data(), parity);
+
+        // a run of bytes is spread over every block by the interleaving, & the header has its own parity
+        for byte in protected[200..300].iter_mut() {
+            *byte = !*byte;
+        }
+        protected[0] ^= 0xff;
+        protected[9] ^= 0x01;
+
+        let recovered = recover(&protected).unwrap();
+        assert_eq!(recovered.data, testing_data());
+        assert_eq!(recovered.corrected, 102);
+    }
+
+    #[test]
+    fn test_too_many_errors() {
+        let mut protected = protect(&testing_data(), parity_for_percent(5).unwrap());
+        for byte in protected[100..400].iter_mut() {
+            *byte = !*byte;
+        }
+        assert!(recover(&protected).is_err());
+        assert!(!is_protected(b"Not protected at all, just some plain bytes"));
+    }
+
+    #[test]
+    fn test_parity_for_percent() {
+        assert_eq!(parity_for_percent(100).unwrap(), 128);
+        assert_eq!(parity_for_percent(10).unwrap(), 23);
+        assert_eq!(parity_for_percent(1).unwrap(), 3);
+        assert!(parity_for_percent(0).is_err());
+        assert!(parity_for_percent(201).is_err());
+        assert_eq!(protect(b"", 10).len(), protected_length(0, 10));
+    }
+}
@@ -0,0 +1,206 @@
+use sha2::{Digest, Sha256};
+
+use crate::{Error, Result};
+use crate::compression::Algorithm;
+use crate::ecc;
+
+// Envelope layout, all integers big-endian:
+//   0  magic "IENV"
+//   4  format version
+//   5  flags
+//   6  header length, offset of the body (u16)
+//   8  body length (u64)
+//  16  SHA-256 digest (32 bytes), of the plaintext, or of the body when encrypted
+//  48  compression algorithm
+//  49  body
+// Later versions may grow the header, readers skip to the body using the header length
+const MAGIC: [u8; 4] = *b"IENV";
+pub const FORMAT_VERSION: u8 = 1;
+const DIGEST_LENGTH: usize = 32;
+const COMPRESSION_OFFSET: usize = 16 + DIGEST_LENGTH;
+pub const HEADER_LENGTH: usize = COMPRESSION_OFFSET + 1;
+
+pub const FLAG_COMPRESSED: u8 = 1;      // body is compressed
+pub const FLAG_ENCRYPTED: u8 = 1 << 1;  // body is encrypted, see crypto
+pub const FLAG_FRAGMENTED: u8 = 1 << 2; // envelope is split across several chunks
+const KNOWN_FLAGS: u8 = FLAG_COMPRESSED | FLAG_ENCRYPTED | FLAG_FRAGMENTED;
+
+// Container wrapped around hidden data, identifying it & detecting truncation or corruption
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    pub flags: u8,
+    pub compression: Algorithm,
+    pub digest: [u8; DIGEST_LENGTH],
+    pub body: Vec<u8>
+}
+
+impl Envelope {
+    // Creates an envelope around body, the compressed & possibly encrypted form of plaintext
+    pub fn seal(flags: u8, compression: Algorithm, plaintext: &[u8], body: Vec<u8>) -> Envelope {
+        let flags = if compression == Algorithm::None { flags & !FLAG_COMPRESSED } else { flags | FLAG_COMPRESSED };
+        // hashing the plaintext of an encrypted body would let anyone confirm a guessed message
+        let digest = if flags & FLAG_ENCRYPTED != 0 { Sha256::digest(&body) } else { Sha256::digest(plaintext) };
+        Envelope { flags, compression, digest: digest.into(), body }
+    }
+
+    // Check if flag is set
+    pub fn has_flag(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    // Make sure plaintext recovered from the body matches the digest, encrypted bodies are checked when parsed
+    pub fn verify(&self, plaintext: &[u8]) -> Result<()> {
+        if !self.has_flag(FLAG_ENCRYPTED) && Sha256::digest(plaintext)[..] != self.digest {
+            return Err(Error::InvalidData("Hidden data does not match its SHA-256 digest".to_string()));
+        }
+        Ok(())
+    }
+
+    // Convert to bytes as described by the layout above
+    pub fn as_bytes(&self) -> Vec<u8> {
+        MAGIC
+            .iter()
+            .chain([FORMAT_VERSION, self.flags].iter())
+            .chain((HEADER_LENGTH as u16).to_be_bytes().iter())
+            .chain((self.body.len() as u64).to_be_bytes().iter())
+            .chain(self.digest.iter())
+            .chain([self.compression as u8].iter())
+            .chain(self.body.iter())
+            .copied()
+            .collect()
+    }
+
+    // Parse bytes written by as_bytes or a later version with a longer header
+    pub fn from_bytes(bytes: &[u8]) -> Result<Envelope> {
+        if !is_envelope(bytes) {
+            return Err(Error::InvalidData("Data is not an imgcrypt envelope".to_string()));
+        }
+        if bytes.len() < 6 || bytes[4] != FORMAT_VERSION {
+            let version = bytes.get(4).map_or("unknown".to_string(), |version| version.to_string());
+            return Err(Error::InvalidData(format!("Unsupported envelope format version {}", version)));
+        }
+        if bytes.len() < HEADER_LENGTH {
+            return Err(Error::InvalidData("Envelope header is truncated".to_string()));
+        }
+
+        let flags = bytes[5];
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(Error::InvalidData(format!("Envelope uses unsupported flags {:#04x}", flags & !KNOWN_FLAGS)));
+        }
+
+        let header_length = u16::from_be_bytes([bytes[6], bytes[7]]) as usize;
+        if header_length < HEADER_LENGTH || header_length > bytes.len() {
+            return Err(Error::InvalidData(format!("Invalid envelope header length {}", header_length)));
+        }
+
+        let mut body_length = [0; 8];
+        body_length.copy_from_slice(&bytes[8..16]);
+        let body_length = u64::from_be_bytes(body_length);
+        let found = (bytes.len() - header_length) as u64;
+        if body_length != found {
+            return Err(Error::InvalidData(format!("Envelope body should be {} bytes long, found {}", body_length, found)));
+        }
+
+        let mut digest = [0; DIGEST_LENGTH];
+        digest.copy_from_slice(&bytes[16..COMPRESSION_OFFSET]);
+        let compression = Algorithm::try_from(bytes[COMPRESSION_OFFSET])?;
+        if (compression != Algorithm::None) != (flags & FLAG_COMPRESSED != 0) {
+            return Err(Error::InvalidData("Envelope compression flag does not match its compression algorithm".to_string()));
+        }
+        let envelope = Envelope { flags, compression, digest, body: bytes[header_length..].to_vec() };
+
+        if envelope.has_flag(FLAG_ENCRYPTED) && Sha256::digest(&envelope.body)[..] != envelope.digest {
+            return Err(Error::InvalidData("Encrypted data does not match its SHA-256 digest".to_string()));
+        }
+
+        Ok(envelope)
+    }
+}
+
+// Check if data starts with the envelope magic
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+// Check if data is an envelope, as is or protected by error correction
+pub fn holds_envelope(data: &[u8]) -> bool {
+    is_envelope(data) || ecc::is_protected(data)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let envelope = Envelope::seal(FLAG_FRAGMENTED, Algorithm::None, b"Secret", b"Secret".to_vec());
+        let bytes = envelope.as_bytes();
+        assert_eq!(bytes.len(), HEADER_LENGTH + 6);
+
+        let parsed = Envelope::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, envelope);
+        assert!(parsed.has_flag(FLAG_FRAGMENTED));
+        assert!(parsed.verify(b"Secret").is_ok());
+        assert!(parsed.verify(b"Secreu").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_envelope_hashes_body() {
+        let envelope = Envelope::seal(FLAG_ENCRYPTED, Algorithm::None, b"Secret", b"Ciphertext".to_vec());
+        assert_eq!(envelope.digest[..], Sha256::digest(b"Ciphertext")[..]);
+
+        let mut bytes = envelope.as_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(Envelope::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_truncated_envelope() {
+        let bytes = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec()).as_bytes();
+        assert!(Envelope::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Envelope::from_bytes(&bytes[..20]).is_err());
+        assert!(Envelope::from_bytes(&[&bytes[..], b"!"].concat()).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_version_and_flags() {
+        let mut bytes = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec()).as_bytes();
+        bytes[4] = 2;
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap_err().to_string(), "Unsupported envelope format version 2");
+
+        let mut bytes = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec()).as_bytes();
+        bytes[5] = 0x80;
+        assert!(Envelope::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_compressed_envelope() {
+        let envelope = Envelope::seal(0, Algorithm::Zstd, b"Secret", b"Compressed".to_vec());
+        assert!(envelope.has_flag(FLAG_COMPRESSED));
+        assert_eq!(Envelope::from_bytes(&envelope.as_bytes()).unwrap(), envelope);
+
+        let mut bytes = envelope.as_bytes();
+        bytes[5] &= !FLAG_COMPRESSED;
+        assert!(Envelope::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_header_without_compression() {
+        let envelope = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec());
+        let mut bytes = envelope.as_bytes();
+        bytes[6..8].copy_from_slice(&(COMPRESSION_OFFSET as u16).to_be_bytes());
+        bytes.remove(COMPRESSION_OFFSET);
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap_err().to_string(), "Invalid envelope header length 48");
+    }
+
+    #[test]
+    fn test_longer_header_is_skipped() {
+        let envelope = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec());
+        let mut bytes = envelope.as_bytes();
+        bytes[6..8].copy_from_slice(&(HEADER_LENGTH as u16 + 3).to_be_bytes());
+        bytes.splice(HEADER_LENGTH..HEADER_LENGTH, [1, 2, 3]);
+        assert_eq!(Envelope::from_bytes(&bytes).unwrap(), envelope);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{fmt, io};
+use std::string::FromUtf8Error;
+
+// Errors returned by imgcrypt
+#[derive(Debug)]
+pub enum Error {
+    BadSignature,                                                       // input does not start with the PNG signature
+    TruncatedChunk { offset: usize },                                   // input ends inside the chunk starting at offset
+    CrcMismatch { chunk_type: String, expected: u32, actual: u32 },     // CRC stored in chunk (expected) differs from the one calculated (actual)
+    InvalidChunkType(String),                                           // chunk type is not 4 valid ASCII letters
+    LengthOverflow { offset: usize, length: u32 },                      // chunk length exceeds the 2^31 - 1 bytes allowed
+    InvalidData(String),                                                // contents of a well-formed chunk are malformed
+    Usage(String),                                                      // options are missing, out of range or cannot be combined
+    Io(io::Error),
+    Other(String)
+}
+
+impl Error {
+    // Process exit code for the CLI, following the BSD sysexits conventions
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::BadSignature
+            | Error::TruncatedChunk { .. }
+            | Error::CrcMismatch { .. }
+            | Error::InvalidChunkType(_)
+            | Error::LengthOverflow { .. }
+            | Error::InvalidData(_) => 65,                          // EX_DATAERR
+            Error::Usage(_) => 64,                                  // EX_USAGE
+            Error::Io(e) if e.kind() == io::ErrorKind::NotFound => 66,  // EX_NOINPUT
+            Error::Io(_) => 74,                                     // EX_IOERR
+            Error::Other(_) => 1
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::BadSignature => write!(f, "Invalid PNG signature"),
+            Error::TruncatedChunk { offset } => write!(f, "Truncated chunk at offset {}", offset),
+            Error::CrcMismatch { chunk_type, expected, actual } => {
+                write!(f, "CRC mismatch in chunk {}: expected {:#010x}, calculated {:#010x}", chunk_type, expected, actual)
+            },
+            Error::InvalidChunkType(chunk_type) => write!(f, "Invalid chunk type \"{}\"", chunk_type),
+            Error::LengthOverflow { offset, length } => {
+                write!(f, "Chunk length {} at offset {} exceeds the maximum of 2^31 - 1 bytes", length, offset)
+            },
+            Error::InvalidData(message) | Error::Usage(message) => write!(f, "{}", message),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::Other(message) => write!(f, "{}", message)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<FromUtf8Error> for Error {
+    fn from(_: FromUtf8Error) -> Self {
+        Error::InvalidData("Data is not valid UTF-8".to_string())
+    }
+}
+
+impl From<&str> for Error {
+    fn from(message: &str) -> Self {
+        Error::Other(message.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(message: String) -> Self {
+        Error::Other(message)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(Error::BadSignature.exit_code(), 65);
+        assert_eq!(Error::TruncatedChunk { offset: 8 }.exit_code(), 65);
+        assert_eq!(Error::Usage("Chunk type is required".to_string()).exit_code(), 64);
+        assert_eq!(Error::Io(io::Error::from(io::ErrorKind::NotFound)).exit_code(), 66);
+        assert_eq!(Error::Io(io::Error::from(io::ErrorKind::PermissionDenied)).exit_code(), 74);
+        assert_eq!(Error::from("Something went wrong").exit_code(), 1);
+    }
+
+    #[test]
+    fn test_error_display() {
+        let error = Error::CrcMismatch { chunk_type: "RuSt".to_string(), expected: 1, actual: 0xabcdef };
+        assert_eq!(error.to_string(), "CRC mismatch in chunk RuSt: expected 0x00000001, calculated 0x00abcdef");
+        assert_eq!(Error::TruncatedChunk { offset: 33 }.to_string(), "Truncated chunk at offset 33");
+        assert_eq!(Error::InvalidChunkType("Ru1t".to_string()).to_string(), "Invalid chunk type \"Ru1t\"");
+    }
+
+    #[test]
+    fn test_error_conversions() {
+        assert!(matches!(Error::from(io::Error::from(io::ErrorKind::NotFound)), Error::Io(_)));
+        assert!(matches!(Error::from(String::from_utf8(vec![0xff]).unwrap_err()), Error::InvalidData(_)));
+        assert!(matches!(Error::from(format!("{} failed", "Encoding")), Error::Other(_)));
+    }
+}
@@ -0,0 +1,205 @@
+use std::convert::TryFrom;
+
+use crate::{Error, Result};
+
+// PNG scanline filter type, stored as the first byte of each filtered scanline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4
+}
+
+impl FilterType {
+    pub const ALL: [FilterType; 5] = [FilterType::None, FilterType::Sub, FilterType::Up, FilterType::Average, FilterType::Paeth];
+}
+
+impl TryFrom<u8> for FilterType {
+    type Error = &'static str;
+
+    fn try_from(byte: u8) -> std::result::Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(FilterType::None),
+            1 => Ok(FilterType::Sub),
+            2 => Ok(FilterType::Up),
+            3 => Ok(FilterType::Average),
+            4 => Ok(FilterType::Paeth),
+            _ => Err("Invalid scanline filter type")
+        }
+    }
+}
+
+// Paeth predictor as defined in the PNG specification
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+// Predict byte from left (a), above (b) & upper left (c) neighbours
+fn predict(filter_type: FilterType, a: u8, b: u8, c: u8) -> u8 {
+    match filter_type {
+        FilterType::None => 0,
+        FilterType::Sub => a,
+        FilterType::Up => b,
+        FilterType::Average => ((a as u16 + b as u16) / 2) as u8,
+        FilterType::Paeth => paeth(a, b, c)
+    }
+}
+
+// Reverse the filter of a single scanline in place, given the already unfiltered previous scanline
+fn unfilter_row(filter_type: FilterType, row: &mut [u8], previous: &[u8], bpp: usize) {
+    for i in 0..row.len() {
+        let a = if i >= bpp { row[i - bpp] } else { 0 };
+        let c = if i >= bpp { previous[i - bpp] } else { 0 };
+        row[i] = row[i].wrapping_add(predict(filter_type, a, previous[i], c));
+    }
+}
+
+// Apply a filter to a single scanline, given the unfiltered previous scanline
+fn filter_row(filter_type: FilterType, row: &[u8], previous: &[u8], bpp: usize) -> Vec<u8> {
+    (0..row.len())
+        .map(|i| {
+            let a = if i >= bpp { row[i - bpp] } else { 0 };
+            let c = if i >= bpp { previous[i - bpp] } else { 0 };
+            row[i].wrapping_sub(predict(filter_type, a, previous[i], c))
+        })
+        .collect()
+}
+
+// Reverse filtering of decompressed image data, returning the raw scanlines without filter type bytes
+// row_length is the number of bytes in a scanline & bpp the number of bytes per complete pixel, rounded up to 1
+pub fn unfilter(data: &[u8], row_length: usize, height: usize, bpp: usize) -> Result<Vec<u8>> {
+    if data.len() < (row_length + 1) * height {
+        return Err(Error::InvalidData("Image data is shorter than the image dimensions require".to_string()));
+    }
+
+    let mut raw = vec![0; row_length * height];
+    let zero_row = vec![0; row_length];
+
+    for y in 0..height {
+        let filtered = &data[y * (row_length + 1)..(y + 1) * (row_length + 1)];
+        let filter_type = FilterType::try_from(filtered[0]).map_err(|e| Error::InvalidData(e.to_string()))?;
+
+        let (done, rest) = raw.split_at_mut(y * row_length);
+        let previous = if y == 0 { &zero_row[..] } else { &done[(y - 1) * row_length..] };
+        let row = &mut rest[..row_length];
+
+        row.copy_from_slice(&filtered[1..]);
+        unfilter_row(filter_type, row, previous, bpp);
+    }
+
+    Ok(raw)
+}
+
+// Filter raw scanlines, choosing per scanline the filter with the smallest sum of absolute differences
+pub fn filter(raw: &[u8], row_length: usize, bpp: usize) -> Vec<u8> {
+    let zero_row = vec![0; row_length];
+    let height = raw.len().checked_div(row_length).unwrap_or(0);
+    let mut filtered = Vec::with_capacity((row_length + 1) * height);
+
+    for y in 0..height {
+        let row = &raw[y * row_length..(y + 1) * row_length];
+        let previous = if y == 0 { &zero_row[..] } else { &raw[(y - 1) * row_length..y * row_length] };
+
+        let (filter_type, bytes) = FilterType::ALL
+            .iter()
+            .map(|&filter_type| (filter_type, filter_row(filter_type, row, previous, bpp)))
+            .min_by_key(|(_, bytes)| bytes.iter().map(|&byte| (byte as i8).unsigned_abs() as u32).sum::<u32>())
+            .unwrap();
+
+        filtered.push(filter_type as u8);
+        filtered.extend(bytes);
+    }
+
+    filtered
+}
+
+// Filter raw scanlines using the same filter type for every scanline
+pub fn filter_with_type(raw: &[u8], row_length: usize, bpp: usize, filter_type: FilterType) -> Vec<u8> {
+    let zero_row = vec![0; row_length];
+    let height = raw.len().checked_div(row_length).unwrap_or(0);
+    let mut filtered = Vec::with_capacity((row_length + 1) * height);
+
+    for y in 0..height {
+        let row = &raw[y * row_length..(y + 1) * row_length];
+        let previous = if y == 0 { &zero_row[..] } else { &raw[(y - 1) * row_length..y * row_length] };
+
+        filtered.push(filter_type as u8);
+        filtered.extend(filter_row(filter_type, row, previous, bpp));
+    }
+
+    filtered
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_raw() -> Vec<u8> {
+        (0..4 * 6 * 5).map(|i: u32| (i * 37 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_filter_roundtrip() {
+        let raw = testing_raw();
+        let filtered = filter(&raw, 24, 4);
+        assert_eq!(filtered.len(), raw.len() + 5);
+        assert_eq!(unfilter(&filtered, 24, 5, 4).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_each_filter_type_roundtrip() {
+        let raw = testing_raw();
+        let zero_row = [0; 24];
+
+        for filter_type in FilterType::ALL {
+            let mut filtered = Vec::new();
+            for y in 0..5 {
+                let previous = if y == 0 { &zero_row[..] } else { &raw[(y - 1) * 24..y * 24] };
+                filtered.push(filter_type as u8);
+                filtered.extend(filter_row(filter_type, &raw[y * 24..(y + 1) * 24], previous, 3));
+            }
+            assert_eq!(unfilter(&filtered, 24, 5, 3).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_filter_with_type() {
+        let raw = testing_raw();
+        let filtered = filter_with_type(&raw, 24, 4, FilterType::Paeth);
+        assert!((0..5).all(|y| filtered[y * 25] == FilterType::Paeth as u8));
+        assert_eq!(unfilter(&filtered, 24, 5, 4).unwrap(), raw);
+    }
+
+    #[test]
+    fn test_paeth_predictor() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+    }
+
+    #[test]
+    fn test_invalid_filter_type() {
+        let data = [5, 1, 2, 3];
+        assert!(unfilter(&data, 3, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let data = [0, 1, 2, 3, 0, 1];
+        assert!(unfilter(&data, 3, 2, 1).is_err());
+    }
+}
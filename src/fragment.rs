@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use rand::rngs::OsRng;
+use rand::RngCore;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+const MAGIC: [u8; 4] = *b"IFRG";
+const FORMAT_VERSION: u8 = 1;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 4 + 4 + 4;
+const LISTED_MISSING: usize = 5;    // missing fragment indices named in the error, the rest are only counted
+
+// Piece of a payload split across several chunks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fragment {
+    pub payload_id: u32,    // random id shared by all fragments of a payload
+    pub index: u32,         // position of this fragment, starting at 0
+    pub total: u32,         // number of fragments making up the payload
+    pub data: Vec<u8>
+}
+
+impl Fragment {
+    // Convert to bytes containing in order: magic, version, payload id, index, total & data
+    pub fn as_bytes(&self) -> Vec<u8> {
+        MAGIC
+            .iter()
+            .chain([FORMAT_VERSION].iter())
+            .chain(self.payload_id.to_be_bytes().iter())
+            .chain(self.index.to_be_bytes().iter())
+            .chain(self.total.to_be_bytes().iter())
+            .chain(self.data.iter())
+            .copied()
+            .collect()
+    }
+
+    // Parse bytes written by as_bytes
+    pub fn from_bytes(bytes: &[u8]) -> Result<Fragment> {
+        if bytes.len() < HEADER_LENGTH || !is_fragment(bytes) {
+            return Err(Error::InvalidData("Malformed fragment header".to_string()));
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(Error::InvalidData(format!("Unsupported fragment format version {}", bytes[4])));
+        }
+
+        let read_u32 = |offset: usize| u32::from_be_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        let fragment = Fragment {
+            payload_id: read_u32(5),
+            index: read_u32(9),
+            total: read_u32(13),
+            data: bytes[HEADER_LENGTH..].to_vec()
+        };
+        if fragment.index >= fragment.total {
+            return Err(Error::InvalidData(format!("Fragment index {} is out of range for {} fragments", fragment.index, fragment.total)));
+        }
+
+        Ok(fragment)
+    }
+}
+
+// Check if chunk data holds a fragment
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+// Split payload into fragments whose chunk data is at most max_chunk_size bytes
+pub fn split(payload: &[u8], max_chunk_size: usize) -> Result<Vec<Fragment>> {
+    if max_chunk_size <= HEADER_LENGTH {
+        return Err(Error::Usage(format!("Maximum chunk size must be larger than the {} byte fragment header", HEADER_LENGTH)));
+    }
+
+    let pieces: Vec<&[u8]> = if payload.is_empty() { vec![payload] } else { payload.chunks(max_chunk_size - HEADER_LENGTH).collect() };
+    let total = u32::try_from(pieces.len()).map_err(|_| Error::Usage("Payload needs too many fragments".to_string()))?;
+    let payload_id = OsRng.next_u32();
+
+    Ok(pieces
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| Fragment { payload_id, index: index as u32, total, data: data.to_vec() })
+        .collect())
+}
+
+// Insert fragments as chunks of the given type, either together before IEND or spread between the existing chunks
+pub fn insert(png: &mut Png, chunk_type: &str, fragments: &[Fragment], interleave: bool) -> Result<()> {
+    let chunks = fragments
+        .iter()
+        .map(|fragment| Ok(Chunk::new(ChunkType::from_str(chunk_type)?, fragment.as_bytes())))
+        .collect::<Result<Vec<Chunk>>>()?;
+
+    let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+    let end = png.insert_position(chunks[0].chunk_type());
+
+    // any gap after IHDR & up to IEND, except between two IDAT chunks which must stay consecutive
+    let gaps: Vec<usize> = if interleave {
+        (1..=end).filter(|&gap| !(types[gap - 1] == "IDAT" && types.get(gap).is_some_and(|t| t == "IDAT"))).collect()
+    } else {
+        Vec::new()
+    };
+
+    // insert from the back so earlier gap indices stay valid, fragments sharing a gap keep their order
+    let count = chunks.len();
+    for (i, chunk) in chunks.into_iter().enumerate().rev() {
+        let index = if gaps.is_empty() { end } else { gaps[i * gaps.len() / count] };
+        png.insert_at(index, chunk)?;
+    }
+
+    Ok(())
+}
+
+// Reassemble the first payload found among chunk data fields holding fragments
+pub fn reassemble<'a>(data: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>> {
+    let mut payload: Option<(u32, u32)> = None;   // id & total of the payload being reassembled
+    let mut pieces: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+
+    for bytes in data.into_iter().filter(|bytes| is_fragment(bytes)) {
+        let fragment = Fragment::from_bytes(bytes)?;
+        let (payload_id, total) = *payload.get_or_insert((fragment.payload_id, fragment.total));
+        if fragment.payload_id != payload_id {
+            continue;
+        }
+        if fragment.total != total {
+            return Err(Error::InvalidData(format!("Fragment {} disagrees on the number of fragments", fragment.index)));
+        }
+        pieces.entry(fragment.index).or_insert(fragment.data);
+    }
+
+    // the total comes from untrusted data, so it is only compared against the fragments present & never iterated in full
+    let (_, total) = payload.ok_or_else(|| Error::InvalidData("No fragments found".to_string()))?;
+    let missing_count = total as usize - pieces.len();
+    if missing_count > 0 {
+        let mut listed: Vec<String> = (0..total)
+            .filter(|index| !pieces.contains_key(index))
+            .take(LISTED_MISSING)
+            .map(|index| index.to_string())
+            .collect();
+        if missing_count > LISTED_MISSING {
+            listed.push("...".to_string());
+        }
+        return Err(Error::InvalidData(format!("Missing {} of {} fragments: {}", missing_count, total, listed.join(", "))));
+    }
+
+    Ok(pieces.into_values().flatten().collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_payload() -> Vec<u8> {
+        (0..1000u32).map(|i| (i * 7 % 256) as u8).collect()
+    }
+
+    fn testing_png() -> Png {
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 4]);
+        Png::from_chunks(vec![chunk("IHDR"), chunk("gAMA"), chunk("IDAT"), chunk("IDAT"), chunk("IDAT"), chunk("IEND")])
+    }
+
+    fn fragment_data(png: &Png) -> Vec<&[u8]> {
+        png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == "ruSt").map(|chunk| chunk.data()).collect()
+    }
+
+    #[test]
+    fn test_split_reassemble() {
+        let payload = testing_payload();
+        let fragments = split(&payload, 117).unwrap();
+        assert_eq!(fragments.len(), 10);
+        assert!(fragments.iter().all(|fragment| fragment.as_bytes().len() <= 117));
+
+        let bytes: Vec<Vec<u8>> = fragments.iter().rev().map(|fragment| fragment.as_bytes()).collect();
+        assert_eq!(reassemble(bytes.iter().map(|b| b.as_slice())).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_missing_fragments() {
+        let fragments = split(&testing_payload(), 117).unwrap();
+        let bytes: Vec<Vec<u8>> = fragments.iter().filter(|fragment| fragment.index % 4 != 1).map(|fragment| fragment.as_bytes()).collect();
+        let error = reassemble(bytes.iter().map(|b| b.as_slice())).unwrap_err();
+        assert_eq!(error.to_string(), "Missing 3 of 10 fragments: 1, 5, 9");
+    }
+
+    #[test]
+    fn test_huge_total() {
+        // a single crafted fragment claiming billions of siblings is reported without listing them all
+        let fragment = Fragment { payload_id: 1, index: 2, total: u32::MAX, data: b"Data".to_vec() };
+        let error = reassemble([fragment.as_bytes().as_slice()]).unwrap_err();
+        assert_eq!(error.to_string(), format!("Missing {} of {} fragments: 0, 1, 3, 4, 5, ...", u32::MAX - 1, u32::MAX));
+    }
+
+    #[test]
+    fn test_reassemble_ignores_other_payloads() {
+        let first = split(b"First payload", 20).unwrap();
+        let second = split(b"Second payload", 20).unwrap();
+        let bytes: Vec<Vec<u8>> = first.iter().chain(second.iter()).map(|fragment| fragment.as_bytes()).collect();
+        assert_eq!(reassemble(bytes.iter().map(|b| b.as_slice())).unwrap(), b"First payload");
+    }
+
+    #[test]
+    fn test_insert_together() {
+        let mut png = testing_png();
+        let fragments = split(&testing_payload(), 300).unwrap();
+        insert(&mut png, "ruSt", &fragments, false).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "gAMA", "IDAT", "IDAT", "IDAT", "ruSt", "ruSt", "ruSt", "ruSt", "IEND"]);
+        assert_eq!(reassemble(fragment_data(&png)).unwrap(), testing_payload());
+    }
+
+    #[test]
+    fn test_insert_interleaved() {
+        let mut png = testing_png();
+        let fragments = split(&testing_payload(), 300).unwrap();
+        insert(&mut png, "ruSt", &fragments, true).unwrap();
+
+        let types: Vec<String> = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["IHDR", "ruSt", "ruSt", "gAMA", "ruSt", "IDAT", "IDAT", "IDAT", "ruSt", "IEND"]);
+        assert_eq!(reassemble(fragment_data(&png)).unwrap(), testing_payload());
+    }
+
+    #[test]
+    fn test_invalid_fragments() {
+        assert!(split(b"Payload", HEADER_LENGTH).is_err());
+
+        let mut bytes = split(b"Payload", 100).unwrap()[0].as_bytes();
+        bytes[9..13].copy_from_slice(&5u32.to_be_bytes());
+        assert!(Fragment::from_bytes(&bytes).is_err());
+        assert!(Fragment::from_bytes(&bytes[..10]).is_err());
+    }
+}
@@ -0,0 +1,261 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::pixels::ColourType;
+
+// Interlace method of a PNG image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterlaceMethod {
+    None = 0,
+    Adam7 = 1
+}
+
+impl TryFrom<u8> for InterlaceMethod {
+    type Error = &'static str;
+
+    fn try_from(byte: u8) -> std::result::Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(InterlaceMethod::None),
+            1 => Ok(InterlaceMethod::Adam7),
+            _ => Err("Invalid interlace method")
+        }
+    }
+}
+
+impl fmt::Display for InterlaceMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterlaceMethod::None => write!(f, "none"),
+            InterlaceMethod::Adam7 => write!(f, "Adam7")
+        }
+    }
+}
+
+// Parsed IHDR chunk holding the image geometry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    colour_type: ColourType,
+    compression_method: u8,     // only deflate (0) is defined
+    filter_method: u8,          // only adaptive filtering (0) is defined
+    interlace_method: InterlaceMethod
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+    const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    // Creates a header for an image using the only defined compression & filter methods
+    pub fn new(width: u32, height: u32, bit_depth: u8, colour_type: ColourType, interlace_method: InterlaceMethod) -> Result<Ihdr> {
+        let ihdr = Ihdr {
+            width,
+            height,
+            bit_depth,
+            colour_type,
+            compression_method: 0,
+            filter_method: 0,
+            interlace_method
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+
+    // Check header against the rules of the PNG specification
+    fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(Error::InvalidData("Image dimensions must be non-zero".to_string()));
+        }
+        if self.width > Ihdr::MAX_DIMENSION || self.height > Ihdr::MAX_DIMENSION {
+            return Err(Error::InvalidData("Image dimensions must not exceed 2^31 - 1".to_string()));
+        }
+        if !self.colour_type.allowed_bit_depths().contains(&self.bit_depth) {
+            return Err(Error::InvalidData(format!("Bit depth {} is not allowed for {} images", self.bit_depth, self.colour_type)));
+        }
+        if self.compression_method != 0 {
+            return Err(Error::InvalidData(format!("Unknown compression method {}", self.compression_method)));
+        }
+        if self.filter_method != 0 {
+            return Err(Error::InvalidData(format!("Unknown filter method {}", self.filter_method)));
+        }
+        Ok(())
+    }
+
+    // Width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    // Height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Number of bits per sample or palette index
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    // Colour type of pixels
+    pub fn colour_type(&self) -> ColourType {
+        self.colour_type
+    }
+
+    // Interlace method of image data
+    pub fn interlace_method(&self) -> InterlaceMethod {
+        self.interlace_method
+    }
+
+    // Convert header to the 13 bytes of an IHDR chunk's data field
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.width
+            .to_be_bytes()
+            .iter()
+            .chain(self.height.to_be_bytes().iter())
+            .chain([
+                self.bit_depth,
+                self.colour_type as u8,
+                self.compression_method,
+                self.filter_method,
+                self.interlace_method as u8
+            ].iter())
+            .copied()
+            .collect()
+    }
+
+    // Convert header to an IHDR chunk
+    pub fn to_chunk(self) -> Chunk {
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), self.as_bytes())
+    }
+}
+
+impl TryFrom<&[u8]> for Ihdr {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Ihdr::LENGTH {
+            return Err(Error::InvalidData(format!("IHDR data must be {} bytes long, found {}", Ihdr::LENGTH, bytes.len())));
+        }
+
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            height: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            bit_depth: bytes[8],
+            colour_type: ColourType::try_from(bytes[9]).map_err(|e| Error::InvalidData(e.to_string()))?,
+            compression_method: bytes[10],
+            filter_method: bytes[11],
+            interlace_method: InterlaceMethod::try_from(bytes[12]).map_err(|e| Error::InvalidData(e.to_string()))?
+        };
+        ihdr.validate()?;
+
+        Ok(ihdr)
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        if chunk.chunk_type().to_string() != "IHDR" {
+            return Err(Error::InvalidData(format!("Expected IHDR chunk, found {}", chunk.chunk_type())));
+        }
+        Ihdr::try_from(chunk.data())
+    }
+}
+
+impl fmt::Display for Ihdr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Width: {}", self.width)?;
+        writeln!(f, "Height: {}", self.height)?;
+        writeln!(f, "Bit depth: {}", self.bit_depth)?;
+        writeln!(f, "Colour type: {} ({})", self.colour_type as u8, self.colour_type)?;
+        writeln!(f, "Compression method: {}", self.compression_method)?;
+        writeln!(f, "Filter method: {}", self.filter_method)?;
+        writeln!(f, "Interlace method: {}", self.interlace_method)?;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        vec![0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_ihdr_from_bytes() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.colour_type(), ColourType::TruecolourAlpha);
+        assert_eq!(ihdr.compression_method, 0);
+        assert_eq!(ihdr.filter_method, 0);
+        assert_eq!(ihdr.interlace_method(), InterlaceMethod::None);
+    }
+
+    #[test]
+    fn test_ihdr_as_bytes() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        assert_eq!(ihdr.as_bytes(), testing_bytes());
+        assert_eq!(Ihdr::try_from(&ihdr.to_chunk()).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_ihdr_new() {
+        let ihdr = Ihdr::new(3, 4, 2, ColourType::Indexed, InterlaceMethod::Adam7).unwrap();
+        assert_eq!(ihdr.as_bytes(), vec![0, 0, 0, 3, 0, 0, 0, 4, 2, 3, 0, 0, 1]);
+    }
+
+    #[test]
+    fn test_invalid_dimensions() {
+        assert!(Ihdr::new(0, 4, 8, ColourType::Truecolour, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(4, 0, 8, ColourType::Truecolour, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(1 << 31, 4, 8, ColourType::Truecolour, InterlaceMethod::None).is_err());
+    }
+
+    #[test]
+    fn test_invalid_bit_depth_for_colour_type() {
+        assert!(Ihdr::new(4, 4, 4, ColourType::Truecolour, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(4, 4, 16, ColourType::Indexed, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(4, 4, 3, ColourType::Greyscale, InterlaceMethod::None).is_err());
+        assert!(Ihdr::new(4, 4, 1, ColourType::Greyscale, InterlaceMethod::None).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_fields() {
+        let mut bytes = testing_bytes();
+        bytes[9] = 5;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[10] = 1;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[11] = 1;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        let mut bytes = testing_bytes();
+        bytes[12] = 2;
+        assert!(Ihdr::try_from(&bytes[..]).is_err());
+
+        assert!(Ihdr::try_from(&testing_bytes()[..12]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_display() {
+        let ihdr = Ihdr::try_from(&testing_bytes()[..]).unwrap();
+        let display = ihdr.to_string();
+        assert!(display.contains("Width: 50"));
+        assert!(display.contains("Colour type: 6 (truecolour with alpha)"));
+    }
+}
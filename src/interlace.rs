@@ -0,0 +1,160 @@
+use crate::{Error, Result};
+use crate::pixels::{self, ColourType, PixelBuffer};
+
+// Adam7 passes as starting column & row followed by the column & row steps, see PNG specification section 8.2
+const PASSES: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2)
+];
+
+// Width & height of the reduced image of a pass, either is zero when the pass is empty
+fn pass_size(pass: usize, width: u32, height: u32) -> (u32, u32) {
+    let (x0, y0, dx, dy) = PASSES[pass];
+    (width.saturating_sub(x0).div_ceil(dx), height.saturating_sub(y0).div_ceil(dy))
+}
+
+// Full image coordinates of every pixel of a pass, in the order the pass stores them
+fn pass_coordinates(pass: usize, width: u32, height: u32) -> impl Iterator<Item = (u32, u32)> {
+    let (x0, y0, dx, dy) = PASSES[pass];
+    let (pass_width, pass_height) = pass_size(pass, width, height);
+    (0..pass_height).flat_map(move |y| (0..pass_width).map(move |x| (x0 + x * dx, y0 + y * dy)))
+}
+
+// Number of bytes of filtered scanlines in each pass, zero for empty passes
+fn pass_lengths(width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<Vec<usize>> {
+    (0..PASSES.len())
+        .map(|pass| {
+            let (pass_width, pass_height) = pass_size(pass, width, height);
+            if pass_width == 0 || pass_height == 0 { Ok(0) } else { pixels::filtered_length(pass_width, pass_height, colour_type, bit_depth) }
+        })
+        .collect()
+}
+
+// Number of bytes of filtered scanlines of all passes of an interlaced image, without allocating it
+pub fn filtered_length(width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<usize> {
+    pass_lengths(width, height, colour_type, bit_depth)?
+        .into_iter()
+        .try_fold(0usize, |total, length| total.checked_add(length))
+        .ok_or_else(|| Error::InvalidData(format!("Image of {}x{} pixels is too large", width, height)))
+}
+
+// Decode Adam7 interlaced image data, each pass being a separately filtered reduced image, into a pixel buffer
+pub fn decode(data: &[u8], width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<PixelBuffer> {
+    // check the data is all there before allocating for the dimensions in an untrusted header
+    let lengths = pass_lengths(width, height, colour_type, bit_depth)?;
+    if data.len() < filtered_length(width, height, colour_type, bit_depth)? {
+        return Err(Error::InvalidData("Image data is shorter than the interlaced image dimensions require".to_string()));
+    }
+
+    let mut pixels = PixelBuffer::new(width, height, colour_type, bit_depth)?;
+    let channel_count = colour_type.channel_count();
+    let mut offset = 0;
+
+    for (pass, length) in lengths.into_iter().enumerate() {
+        if length == 0 {
+            continue;
+        }
+
+        let (pass_width, pass_height) = pass_size(pass, width, height);
+        let pass_data = &data[offset..offset + length];
+        let reduced = PixelBuffer::decode(pass_data, pass_width, pass_height, colour_type, bit_depth)?;
+        offset += length;
+
+        for (i, (x, y)) in pass_coordinates(pass, width, height).enumerate() {
+            for channel in 0..channel_count {
+                pixels.set_sample(pixels.index(x, y, channel), reduced.sample(i * channel_count + channel));
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+// Encode pixels into Adam7 interlaced image data ready to be compressed
+pub fn encode(pixels: &PixelBuffer) -> Result<Vec<u8>> {
+    let (width, height) = (pixels.width(), pixels.height());
+    let channel_count = pixels.colour_type().channel_count();
+    let mut data = Vec::new();
+
+    for pass in 0..PASSES.len() {
+        let (pass_width, pass_height) = pass_size(pass, width, height);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+
+        let mut reduced = PixelBuffer::new(pass_width, pass_height, pixels.colour_type(), pixels.bit_depth())?;
+        for (i, (x, y)) in pass_coordinates(pass, width, height).enumerate() {
+            for channel in 0..channel_count {
+                reduced.set_sample(i * channel_count + channel, pixels.sample(pixels.index(x, y, channel)));
+            }
+        }
+        data.extend(reduced.encode());
+    }
+
+    Ok(data)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_pixels(width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> PixelBuffer {
+        let mut pixels = PixelBuffer::new(width, height, colour_type, bit_depth).unwrap();
+        for index in 0..pixels.len() {
+            pixels.set_sample(index, (index * 37 % 65536) as u16);
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_pass_sizes() {
+        // 8x8 image splits into 1, 1, 2, 4, 8, 16 & 32 pixels
+        let sizes: Vec<(u32, u32)> = (0..7).map(|pass| pass_size(pass, 8, 8)).collect();
+        assert_eq!(sizes, vec![(1, 1), (1, 1), (2, 1), (2, 2), (4, 2), (4, 4), (8, 4)]);
+
+        // a single pixel belongs to the first pass only
+        assert!((1..7).all(|pass| { let (w, h) = pass_size(pass, 1, 1); w == 0 || h == 0 }));
+
+        // every pixel is visited exactly once
+        let mut visited = vec![0; 13 * 11];
+        for pass in 0..7 {
+            for (x, y) in pass_coordinates(pass, 13, 11) {
+                visited[(y * 13 + x) as usize] += 1;
+            }
+        }
+        assert!(visited.iter().all(|&count| count == 1));
+    }
+
+    #[test]
+    fn test_interlace_roundtrip() {
+        for (width, height, colour_type, bit_depth) in [
+            (13, 11, ColourType::TruecolourAlpha, 8),
+            (1, 1, ColourType::Greyscale, 16),
+            (5, 3, ColourType::Greyscale, 1),
+            (9, 2, ColourType::Indexed, 4),
+            (3, 9, ColourType::Truecolour, 16)
+        ] {
+            let pixels = testing_pixels(width, height, colour_type, bit_depth);
+            let data = encode(&pixels).unwrap();
+            assert_eq!(decode(&data, width, height, colour_type, bit_depth).unwrap(), pixels);
+        }
+    }
+
+    #[test]
+    fn test_first_pass_layout() {
+        // the first pass of an 8-bit greyscale image holds the top left pixel as a 1x1 image behind its filter type byte
+        let pixels = testing_pixels(8, 8, ColourType::Greyscale, 8);
+        let data = encode(&pixels).unwrap();
+        assert_eq!(data[1], pixels.sample(0) as u8);
+        // rows of 1, 1, 2, 2, 4, 4 & 8 pixels, each with a filter type byte
+        assert_eq!(data.len(), 2 + 2 + 3 + 2 * 3 + 2 * 5 + 4 * 5 + 4 * 9);
+        assert_eq!(filtered_length(8, 8, ColourType::Greyscale, 8).unwrap(), data.len());
+        assert!(decode(&data[..data.len() - 1], 8, 8, ColourType::Greyscale, 8).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+use std::{fmt, fs, io::Write, path::Path};
+
+use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+use ed25519_dalek::Signer as _;
+use rand::rngs::OsRng;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::{Error, Result};
+
+const KEY_LENGTH: usize = 32;
+pub const SIGNATURE_LENGTH: usize = 64;
+
+// X25519 secret key used to open payloads encrypted to its recipient
+pub struct Identity(StaticSecret);
+
+// X25519 public key that payloads can be encrypted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recipient(PublicKey);
+
+// Ed25519 secret key used to sign embedded payloads
+pub struct SigningIdentity(SigningKey);
+
+// Ed25519 public key that signatures are verified against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signer(VerifyingKey);
+
+impl Identity {
+    // Generate a new random identity
+    pub fn generate() -> Identity {
+        Identity(StaticSecret::random_from_rng(OsRng))
+    }
+
+    // Recipient corresponding to this identity
+    pub fn recipient(&self) -> Recipient {
+        Recipient(PublicKey::from(&self.0))
+    }
+
+    // Underlying X25519 secret
+    pub fn secret(&self) -> &StaticSecret {
+        &self.0
+    }
+
+    // Read identity from a file containing the hex encoded secret key
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Identity> {
+        let bytes = decode_key(&fs::read_to_string(path)?)?;
+        Ok(Identity(StaticSecret::from(bytes)))
+    }
+
+    // Write hex encoded secret key to file, failing if it exists unless overwrite is set
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P, overwrite: bool) -> Result<()> {
+        write_secret_file(path, &self.0.to_bytes(), overwrite)
+    }
+}
+
+impl Recipient {
+    // Underlying X25519 public key
+    pub fn public_key(&self) -> &PublicKey {
+        &self.0
+    }
+
+    // Public key as bytes
+    pub fn bytes(&self) -> [u8; KEY_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    // Parse recipient from either a hex encoded public key or a path to a file containing one
+    pub fn from_arg(arg: &str) -> Result<Recipient> {
+        let path = Path::new(arg);
+        if path.is_file() {
+            return Recipient::from_file(path);
+        }
+        arg.parse()
+    }
+
+    // Read recipient from a file containing the hex encoded public key
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Recipient> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    // Write hex encoded public key to file
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, format!("{}\n", self))?;
+        Ok(())
+    }
+}
+
+impl SigningIdentity {
+    // Generate a new random signing key
+    pub fn generate() -> SigningIdentity {
+        SigningIdentity(SigningKey::generate(&mut OsRng))
+    }
+
+    // Public key corresponding to this signing key
+    pub fn signer(&self) -> Signer {
+        Signer(self.0.verifying_key())
+    }
+
+    // Sign message, returning the 64 byte Ed25519 signature
+    pub fn sign(&self, message: &[u8]) -> [u8; SIGNATURE_LENGTH] {
+        self.0.sign(message).to_bytes()
+    }
+
+    // Read signing key from a file containing the hex encoded secret key
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SigningIdentity> {
+        let bytes = decode_key(&fs::read_to_string(path)?)?;
+        Ok(SigningIdentity(SigningKey::from_bytes(&bytes)))
+    }
+
+    // Write hex encoded secret key to file, failing if it exists unless overwrite is set
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P, overwrite: bool) -> Result<()> {
+        write_secret_file(path, &self.0.to_bytes(), overwrite)
+    }
+}
+
+impl Signer {
+    // Public key as bytes
+    pub fn bytes(&self) -> [u8; KEY_LENGTH] {
+        self.0.to_bytes()
+    }
+
+    // Check signature of message against this public key
+    pub fn verify(&self, message: &[u8], signature: &[u8; SIGNATURE_LENGTH]) -> bool {
+        self.0.verify_strict(message, &Signature::from_bytes(signature)).is_ok()
+    }
+
+    // Parse signer from either a hex encoded public key or a path to a file containing one
+    pub fn from_arg(arg: &str) -> Result<Signer> {
+        let path = Path::new(arg);
+        if path.is_file() {
+            return fs::read_to_string(path)?.parse();
+        }
+        arg.parse()
+    }
+
+    // Write hex encoded public key to file
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, format!("{}\n", self))?;
+        Ok(())
+    }
+}
+
+impl TryFrom<[u8; KEY_LENGTH]> for Signer {
+    type Error = crate::Error;
+
+    fn try_from(bytes: [u8; KEY_LENGTH]) -> Result<Self> {
+        let key = VerifyingKey::from_bytes(&bytes).map_err(|_| Error::InvalidData("Invalid Ed25519 public key".to_string()))?;
+        Ok(Signer(key))
+    }
+}
+
+impl std::str::FromStr for Signer {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Signer::try_from(decode_key(s)?)
+    }
+}
+
+impl fmt::Display for Signer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.bytes()))
+    }
+}
+
+impl From<[u8; KEY_LENGTH]> for Recipient {
+    fn from(bytes: [u8; KEY_LENGTH]) -> Self {
+        Recipient(PublicKey::from(bytes))
+    }
+}
+
+impl std::str::FromStr for Recipient {
+    type Err = crate::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(Recipient::from(decode_key(s)?))
+    }
+}
+
+impl fmt::Display for Recipient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.bytes()))
+    }
+}
+
+// Write hex encoded secret key to file, readable only by the owner on unix
+// Without overwrite the file is created atomically, so an existing key is never replaced
+fn write_secret_file<P: AsRef<Path>>(path: P, key: &[u8], overwrite: bool) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    if overwrite {
+        options.write(true).create(true).truncate(true);
+    } else {
+        options.write(true).create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(path)?;
+    // the mode only applies to new files, an overwritten one keeps its permissions otherwise
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    writeln!(file, "{}", hex::encode(key))?;
+    Ok(())
+}
+
+// Decode a hex encoded 32 byte key, ignoring surrounding whitespace
+fn decode_key(s: &str) -> Result<[u8; KEY_LENGTH]> {
+    let bytes = hex::decode(s.trim()).map_err(|e| Error::InvalidData(format!("Invalid key encoding: {}", e)))?;
+    bytes.try_into().map_err(|_| Error::InvalidData("Key must be 32 bytes long".to_string()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::str::FromStr;
+    use crate::Error;
+
+    #[test]
+    fn test_recipient_from_identity() {
+        let identity = Identity::generate();
+        let secret = StaticSecret::from(identity.secret().to_bytes());
+        assert_eq!(identity.recipient().bytes(), PublicKey::from(&secret).to_bytes());
+    }
+
+    #[test]
+    fn test_recipient_string_roundtrip() {
+        let recipient = Identity::generate().recipient();
+        let parsed = Recipient::from_str(&recipient.to_string()).unwrap();
+        assert_eq!(parsed, recipient);
+    }
+
+    #[test]
+    fn test_invalid_recipient() {
+        assert!(Recipient::from_str("not a key").is_err());
+        assert!(Recipient::from_str("abcd").is_err());
+    }
+
+    #[test]
+    fn test_key_files_roundtrip() {
+        let dir = std::env::temp_dir();
+        let identity_path = dir.join(format!("imgcrypt-test-{}.key", std::process::id()));
+        let recipient_path = identity_path.with_extension("pub");
+
+        let identity = Identity::generate();
+        identity.write_to_file(&identity_path, false).unwrap();
+        identity.recipient().write_to_file(&recipient_path).unwrap();
+
+        let read_identity = Identity::from_file(&identity_path).unwrap();
+        let read_recipient = Recipient::from_arg(recipient_path.to_str().unwrap()).unwrap();
+
+        assert_eq!(read_identity.recipient(), identity.recipient());
+        assert_eq!(read_recipient, identity.recipient());
+
+        fs::remove_file(identity_path).unwrap();
+        fs::remove_file(recipient_path).unwrap();
+    }
+
+    #[test]
+    fn test_existing_key_file_kept() {
+        let path = std::env::temp_dir().join(format!("imgcrypt-test-{}-existing.key", std::process::id()));
+        let identity = Identity::generate();
+        identity.write_to_file(&path, false).unwrap();
+
+        let result = Identity::generate().write_to_file(&path, false);
+        assert!(matches!(result, Err(Error::Io(e)) if e.kind() == io::ErrorKind::AlreadyExists));
+        assert_eq!(Identity::from_file(&path).unwrap().recipient(), identity.recipient());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_overwritten_key_file_is_private() {
+        let path = std::env::temp_dir().join(format!("imgcrypt-test-{}-overwritten.key", std::process::id()));
+        fs::write(&path, "world readable").unwrap();
+        #[cfg(unix)]
+        fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o644)).unwrap();
+
+        let identity = SigningIdentity::generate();
+        identity.write_to_file(&path, true).unwrap();
+        assert_eq!(SigningIdentity::from_file(&path).unwrap().signer(), identity.signer());
+        #[cfg(unix)]
+        assert_eq!(std::os::unix::fs::PermissionsExt::mode(&fs::metadata(&path).unwrap().permissions()) & 0o777, 0o600);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let identity = SigningIdentity::generate();
+        let signature = identity.sign(b"Message");
+        assert!(identity.signer().verify(b"Message", &signature));
+        assert!(!identity.signer().verify(b"Massage", &signature));
+        assert!(!SigningIdentity::generate().signer().verify(b"Message", &signature));
+    }
+
+    #[test]
+    fn test_signing_key_files_roundtrip() {
+        let dir = std::env::temp_dir();
+        let key_path = dir.join(format!("imgcrypt-test-{}-sign.key", std::process::id()));
+        let signer_path = key_path.with_extension("pub");
+
+        let identity = SigningIdentity::generate();
+        identity.write_to_file(&key_path, false).unwrap();
+        identity.signer().write_to_file(&signer_path).unwrap();
+
+        let read_identity = SigningIdentity::from_file(&key_path).unwrap();
+        let read_signer = Signer::from_arg(signer_path.to_str().unwrap()).unwrap();
+
+        assert_eq!(read_identity.signer(), identity.signer());
+        assert_eq!(read_signer, identity.signer());
+        assert_eq!(Signer::from_str(&identity.signer().to_string()).unwrap(), identity.signer());
+
+        fs::remove_file(key_path).unwrap();
+        fs::remove_file(signer_path).unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ihdr::Ihdr;
+use crate::pixels::ColourType;
+use crate::png::Png;
+
+const KNOWN_CRITICAL_CHUNKS: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+const SINGLETON_CHUNKS: [&str; 17] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "cICP", "gAMA", "iCCP", "mDCV", "cLLI", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "eXIf", "pHYs", "tIME"
+];
+const BEFORE_PALETTE_CHUNKS: [&str; 8] = ["cHRM", "cICP", "gAMA", "iCCP", "mDCV", "cLLI", "sBIT", "sRGB"];  // must precede PLTE & IDAT
+const AFTER_PALETTE_CHUNKS: [&str; 3] = ["bKGD", "hIST", "tRNS"];                                          // must follow PLTE & precede IDAT
+const BEFORE_IMAGE_DATA_CHUNKS: [&str; 3] = ["eXIf", "pHYs", "sPLT"];                                      // must precede IDAT
+
+// How serious a finding is, errors break the PNG specification while warnings are tolerated by most decoders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error")
+        }
+    }
+}
+
+// Single spec-conformance problem found in a PNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub chunk_index: Option<usize>,     // index of the offending chunk, None for problems with the file as a whole
+    pub message: String
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.chunk_index {
+            Some(index) => write!(f, "{} [chunk {}]: {}", self.severity, index, self.message),
+            None => write!(f, "{}: {}", self.severity, self.message)
+        }
+    }
+}
+
+// Collects findings while checking a PNG
+struct Linter<'a> {
+    types: Vec<String>,     // chunk types in file order
+    png: &'a Png,
+    findings: Vec<Finding>
+}
+
+impl<'a> Linter<'a> {
+    fn new(png: &'a Png) -> Linter<'a> {
+        let types = png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        Linter { types, png, findings: Vec::new() }
+    }
+
+    fn report(&mut self, severity: Severity, chunk_index: Option<usize>, message: String) {
+        self.findings.push(Finding { severity, chunk_index, message });
+    }
+
+    fn first(&self, chunk_type: &str) -> Option<usize> {
+        self.types.iter().position(|t| t == chunk_type)
+    }
+
+    // IHDR first, IEND last
+    fn check_layout(&mut self) {
+        match self.first("IHDR") {
+            Some(0) => {},
+            Some(index) => self.report(Severity::Error, Some(index), "IHDR must be the first chunk".to_string()),
+            None => self.report(Severity::Error, None, "Missing IHDR chunk".to_string())
+        }
+
+        let last = self.types.len().checked_sub(1);
+        match self.first("IEND") {
+            Some(index) if Some(index) == last => {},
+            Some(index) => self.report(Severity::Error, Some(index), format!("IEND must be the last chunk, followed by {} chunks", self.types.len() - 1 - index)),
+            None => self.report(Severity::Error, None, "Missing IEND chunk".to_string())
+        }
+    }
+
+    // Nothing after IEND, decoders ignore it but it often carries hidden or leftover data
+    fn check_trailer(&mut self) {
+        let length = self.png.trailer().len();
+        if length > 0 {
+            self.report(Severity::Warning, None, format!("{} bytes of trailing data after IEND", length));
+        }
+    }
+
+    // IDAT present & consecutive
+    fn check_image_data(&mut self) {
+        let indices: Vec<usize> = (0..self.types.len()).filter(|&i| self.types[i] == "IDAT").collect();
+        if indices.is_empty() {
+            self.report(Severity::Error, None, "Missing IDAT chunk".to_string());
+        }
+        for pair in indices.windows(2) {
+            if pair[1] != pair[0] + 1 {
+                self.report(Severity::Error, Some(pair[1]), format!("IDAT chunks must be consecutive, found {} in between", self.types[pair[0] + 1]));
+            }
+        }
+    }
+
+    // Critical chunks known, reserved bit clear, singleton chunks not repeated
+    fn check_chunk_types(&mut self) {
+        let mut seen: HashMap<String, usize> = HashMap::new();
+
+        for (index, chunk) in self.png.chunks().iter().enumerate() {
+            let chunk_type = chunk.chunk_type();
+            let name = chunk_type.to_string();
+
+            if chunk_type.is_critical() && !KNOWN_CRITICAL_CHUNKS.contains(&name.as_str()) {
+                self.report(Severity::Error, Some(index), format!("Unknown critical chunk {}", name));
+            }
+            if !chunk_type.is_reserved_bit_valid() {
+                self.report(Severity::Error, Some(index), format!("Chunk type {} has the reserved bit set", name));
+            }
+            if SINGLETON_CHUNKS.contains(&name.as_str()) {
+                if let Some(&first) = seen.get(&name) {
+                    let severity = if chunk_type.is_critical() { Severity::Error } else { Severity::Warning };
+                    self.report(severity, Some(index), format!("Duplicate {} chunk, first seen at chunk {}", name, first));
+                }
+            }
+            seen.entry(name).or_insert(index);
+        }
+    }
+
+    // PLTE presence, position & size match the colour type
+    fn check_palette(&mut self, ihdr: &Ihdr) {
+        let palette = self.first("PLTE");
+        let colour_type = ihdr.colour_type();
+
+        let index = match (palette, colour_type) {
+            (None, ColourType::Indexed) => {
+                self.report(Severity::Error, None, "Indexed-colour image is missing a PLTE chunk".to_string());
+                return;
+            },
+            (None, _) => return,
+            (Some(index), ColourType::Greyscale | ColourType::GreyscaleAlpha) => {
+                self.report(Severity::Error, Some(index), format!("PLTE chunk is not allowed in {} images", colour_type));
+                return;
+            },
+            (Some(index), _) => index
+        };
+
+        if self.first("IDAT").is_some_and(|idat| idat < index) {
+            self.report(Severity::Error, Some(index), "PLTE must precede the first IDAT chunk".to_string());
+        }
+
+        let length = self.png.chunks()[index].data().len();
+        let max_entries = if colour_type == ColourType::Indexed { 1 << ihdr.bit_depth() } else { 256 };
+        if length == 0 || !length.is_multiple_of(3) {
+            self.report(Severity::Error, Some(index), format!("PLTE length {} is not a positive multiple of 3", length));
+        } else if length / 3 > max_entries {
+            self.report(Severity::Error, Some(index), format!("PLTE has {} entries, at most {} are allowed", length / 3, max_entries));
+        }
+    }
+
+    // Ancillary chunks placed relative to PLTE & IDAT as the specification requires
+    fn check_ancillary_order(&mut self) {
+        let palette = self.first("PLTE");
+        let image_data = self.first("IDAT");
+
+        for index in 0..self.types.len() {
+            let name = self.types[index].clone();
+            let after_image_data = image_data.is_some_and(|idat| index > idat);
+
+            if BEFORE_PALETTE_CHUNKS.contains(&name.as_str()) && (after_image_data || palette.is_some_and(|plte| index > plte)) {
+                self.report(Severity::Warning, Some(index), format!("{} must precede PLTE & IDAT", name));
+            }
+            if AFTER_PALETTE_CHUNKS.contains(&name.as_str()) {
+                if palette.is_some_and(|plte| index < plte) {
+                    self.report(Severity::Warning, Some(index), format!("{} must follow PLTE", name));
+                }
+                if after_image_data {
+                    self.report(Severity::Warning, Some(index), format!("{} must precede IDAT", name));
+                }
+            }
+            if BEFORE_IMAGE_DATA_CHUNKS.contains(&name.as_str()) && after_image_data {
+                self.report(Severity::Warning, Some(index), format!("{} must precede IDAT", name));
+            }
+        }
+
+        if let Some(index) = self.first("hIST").filter(|_| palette.is_none()) {
+            self.report(Severity::Error, Some(index), "hIST requires a PLTE chunk".to_string());
+        }
+    }
+
+    fn run(mut self) -> Vec<Finding> {
+        self.check_layout();
+        self.check_trailer();
+        self.check_image_data();
+        self.check_chunk_types();
+
+        match self.png.ihdr() {
+            Ok(ihdr) => self.check_palette(&ihdr),
+            Err(e) => {
+                let index = self.first("IHDR");
+                if index.is_some() {
+                    self.report(Severity::Error, index, format!("Invalid IHDR: {}", e));
+                }
+            }
+        }
+
+        self.check_ancillary_order();
+
+        self.findings.sort_by_key(|finding| (finding.chunk_index, std::cmp::Reverse(finding.severity)));
+        self.findings
+    }
+}
+
+// Check the chunk structure of a PNG against the rules of the PNG specification
+pub fn lint(png: &Png) -> Vec<Finding> {
+    Linter::new(png).run()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn ihdr(colour_type: u8, bit_depth: u8) -> Chunk {
+        chunk("IHDR", vec![0, 0, 0, 4, 0, 0, 0, 4, bit_depth, colour_type, 0, 0, 0])
+    }
+
+    fn testing_png(chunks: Vec<Chunk>) -> Png {
+        Png::from_chunks(chunks)
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<String> {
+        findings.iter().map(|finding| finding.to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let png = testing_png(vec![ihdr(6, 8), chunk("gAMA", vec![0; 4]), chunk("IDAT", vec![]), chunk("IDAT", vec![]), chunk("ruSt", vec![1]), chunk("IEND", vec![])]);
+        assert!(lint(&png).is_empty());
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let png = testing_png(vec![chunk("IDAT", vec![]), ihdr(6, 8), chunk("IEND", vec![]), chunk("ruSt", vec![1])]);
+        let findings = lint(&png);
+        assert_eq!(messages(&findings), vec![
+            "error [chunk 1]: IHDR must be the first chunk",
+            "error [chunk 2]: IEND must be the last chunk, followed by 1 chunks"
+        ]);
+
+        let png = testing_png(vec![ihdr(6, 8)]);
+        let findings = lint(&png);
+        assert!(findings.iter().all(|finding| finding.chunk_index.is_none() && finding.severity == Severity::Error));
+        assert_eq!(findings.len(), 2);
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut png = testing_png(vec![ihdr(6, 8), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        png.set_trailer(b"Hidden".to_vec());
+        assert_eq!(messages(&lint(&png)), vec!["warning: 6 bytes of trailing data after IEND"]);
+    }
+
+    #[test]
+    fn test_non_consecutive_image_data() {
+        let png = testing_png(vec![ihdr(6, 8), chunk("IDAT", vec![]), chunk("tEXt", vec![]), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec!["error [chunk 3]: IDAT chunks must be consecutive, found tEXt in between"]);
+    }
+
+    #[test]
+    fn test_palette_rules() {
+        let png = testing_png(vec![ihdr(3, 8), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec!["error: Indexed-colour image is missing a PLTE chunk"]);
+
+        let png = testing_png(vec![ihdr(0, 8), chunk("PLTE", vec![0; 3]), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec!["error [chunk 1]: PLTE chunk is not allowed in greyscale images"]);
+
+        let png = testing_png(vec![ihdr(3, 1), chunk("PLTE", vec![0; 9]), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec!["error [chunk 1]: PLTE has 3 entries, at most 2 are allowed"]);
+
+        let png = testing_png(vec![ihdr(2, 8), chunk("IDAT", vec![]), chunk("PLTE", vec![0; 4]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec![
+            "error [chunk 2]: PLTE must precede the first IDAT chunk",
+            "error [chunk 2]: PLTE length 4 is not a positive multiple of 3"
+        ]);
+    }
+
+    #[test]
+    fn test_chunk_type_rules() {
+        let png = testing_png(vec![ihdr(6, 8), chunk("IDAT", vec![]), chunk("RuSt", vec![]), chunk("ruet", vec![]), chunk("IEND", vec![]), chunk("IEND", vec![])]);
+        let findings = lint(&png);
+        assert!(findings.contains(&Finding { severity: Severity::Error, chunk_index: Some(2), message: "Unknown critical chunk RuSt".to_string() }));
+        assert!(findings.contains(&Finding { severity: Severity::Error, chunk_index: Some(3), message: "Chunk type ruet has the reserved bit set".to_string() }));
+        assert!(findings.contains(&Finding { severity: Severity::Error, chunk_index: Some(5), message: "Duplicate IEND chunk, first seen at chunk 4".to_string() }));
+    }
+
+    #[test]
+    fn test_ancillary_order() {
+        let png = testing_png(vec![ihdr(3, 8), chunk("tRNS", vec![0]), chunk("PLTE", vec![0; 3]), chunk("IDAT", vec![]), chunk("gAMA", vec![0; 4]), chunk("gAMA", vec![0; 4]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec![
+            "warning [chunk 1]: tRNS must follow PLTE",
+            "warning [chunk 4]: gAMA must precede PLTE & IDAT",
+            "warning [chunk 5]: Duplicate gAMA chunk, first seen at chunk 4",
+            "warning [chunk 5]: gAMA must precede PLTE & IDAT"
+        ]);
+    }
+
+    #[test]
+    fn test_newer_ancillary_order() {
+        let png = testing_png(vec![ihdr(6, 8), chunk("IDAT", vec![]), chunk("cICP", vec![0; 4]), chunk("eXIf", vec![]), chunk("eXIf", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec![
+            "warning [chunk 2]: cICP must precede PLTE & IDAT",
+            "warning [chunk 3]: eXIf must precede IDAT",
+            "warning [chunk 4]: Duplicate eXIf chunk, first seen at chunk 3",
+            "warning [chunk 4]: eXIf must precede IDAT"
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_ihdr() {
+        let png = testing_png(vec![ihdr(2, 4), chunk("IDAT", vec![]), chunk("IEND", vec![])]);
+        assert_eq!(messages(&lint(&png)), vec!["error [chunk 0]: Invalid IHDR: Bit depth 4 is not allowed for truecolour images"]);
+    }
+}
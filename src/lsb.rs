@@ -0,0 +1,593 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+
+use crate::{Error, Result};
+use crate::ihdr::Ihdr;
+use crate::pixels::{ColourType, PixelBuffer};
+use crate::png::Png;
+
+pub const LENGTH_PREFIX_SIZE: usize = 4;    // payload length stored in front of the payload bits
+
+// Image channels whose samples carry payload bits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels { red: true, green: true, blue: true, alpha: false }
+    }
+}
+
+impl FromStr for Channels {
+    type Err = &'static str;
+
+    // Parse channels from letters r, g, b & a, e.g. "rgb"
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut channels = Channels { red: false, green: false, blue: false, alpha: false };
+
+        for c in s.chars() {
+            match c.to_ascii_lowercase() {
+                'r' => channels.red = true,
+                'g' => channels.green = true,
+                'b' => channels.blue = true,
+                'a' => channels.alpha = true,
+                _ => return Err("Channels must only consist of the letters r, g, b & a")
+            }
+        }
+
+        if s.is_empty() {
+            return Err("At least one channel must be selected");
+        }
+
+        Ok(channels)
+    }
+}
+
+impl fmt::Display for Channels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (selected, letter) in [(self.red, 'r'), (self.green, 'g'), (self.blue, 'b'), (self.alpha, 'a')] {
+            if selected {
+                write!(f, "{}", letter)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Options controlling which bits of the image carry the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LsbOptions {
+    pub channels: Channels,
+    pub bits_per_channel: u8,               // number of low bits used in each selected sample, 1 to 8
+    pub scatter_key: Option<[u8; 32]>,      // visit samples in an order only this key reproduces, rather than top to bottom
+    pub translucent_only: bool,             // only use pixels that are already partly transparent
+    pub matrix_bits: Option<u8>             // hide this many bits in every 2^k - 1 samples with Hamming matrix encoding, changing at most one
+}
+
+impl Default for LsbOptions {
+    fn default() -> Self {
+        LsbOptions { channels: Channels::default(), bits_per_channel: 1, scatter_key: None, translucent_only: false, matrix_bits: None }
+    }
+}
+
+// Which of the samples of a pixel are selected
+fn selected_samples(colour_type: ColourType, channels: Channels) -> Vec<bool> {
+    let grey = channels.red || channels.green || channels.blue;
+    match colour_type {
+        ColourType::Greyscale | ColourType::Indexed => vec![grey],
+        ColourType::GreyscaleAlpha => vec![grey, channels.alpha],
+        ColourType::Truecolour => vec![channels.red, channels.green, channels.blue],
+        ColourType::TruecolourAlpha => vec![channels.red, channels.green, channels.blue, channels.alpha]
+    }
+}
+
+// Samples modified by embedding, fewer changes per embedded bit being harder to detect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Changes {
+    pub bits: usize,        // message bits embedded, including the length prefix
+    pub carriers: usize,    // samples that carry them
+    pub changed: usize      // samples whose value changed
+}
+
+impl Changes {
+    // Average number of changed samples for every embedded bit
+    pub fn per_bit(&self) -> f64 {
+        if self.bits == 0 { 0.0 } else { self.changed as f64 / self.bits as f64 }
+    }
+}
+
+// Indices of every selected sample in the pixel buffer, in the order they carry payload bits
+fn carrier_indices(pixels: &PixelBuffer, options: &LsbOptions) -> Vec<usize> {
+    let selected = selected_samples(pixels.colour_type(), options.channels);
+    let channel_count = selected.len();
+    let mut indices: Vec<usize> = (0..pixels.len())
+        .filter(|index| selected[index % channel_count])
+        .filter(|index| !options.translucent_only || is_translucent(pixels, index - index % channel_count + channel_count - 1, options))
+        .collect();
+    if let Some(key) = options.scatter_key {
+        shuffle(&mut indices, key);
+    }
+    indices
+}
+
+// Check if the alpha sample at index is below fully opaque whatever its low bits are, so embedding never changes the answer
+fn is_translucent(pixels: &PixelBuffer, alpha_index: usize, options: &LsbOptions) -> bool {
+    let bits = options.bits_per_channel as u32;
+    (pixels.sample(alpha_index) >> bits) < (pixels.max_sample_value() >> bits)
+}
+
+// Fisher-Yates shuffle driven by ChaCha20 seeded with key, spelled out so the order never changes with the rand crate
+pub fn shuffle(indices: &mut [usize], key: [u8; 32]) {
+    let mut rng = ChaCha20Rng::from_seed(key);
+    for i in (1..indices.len()).rev() {
+        let j = uniform_below(&mut rng, i as u64 + 1) as usize;
+        indices.swap(i, j);
+    }
+}
+
+// Uniformly distributed number below bound, rejecting the values that would bias the modulo
+fn uniform_below(rng: &mut ChaCha20Rng, bound: u64) -> u64 {
+    let zone = u64::MAX - u64::MAX % bound;
+    loop {
+        let value = rng.next_u64();
+        if value < zone {
+            return value % bound;
+        }
+    }
+}
+
+// Make sure the samples of the image described by ihdr can carry payload bits
+fn check_image(ihdr: &Ihdr) -> Result<()> {
+    if ihdr.colour_type() == ColourType::Indexed {
+        return Err(Error::Usage("LSB embedding does not support indexed-colour images, use the palette method".to_string()));
+    }
+    if ihdr.bit_depth() < 8 {
+        return Err(Error::Usage("LSB embedding requires an 8 or 16-bit greyscale or truecolour image".to_string()));
+    }
+    Ok(())
+}
+
+// Decode pixels of PNG, making sure they can carry payload bits
+fn carrier_pixels(png: &Png, options: &LsbOptions) -> Result<PixelBuffer> {
+    let ihdr = png.ihdr()?;
+    check_image(&ihdr)?;
+    check_channels(&ihdr, options)?;
+    png.pixels()
+}
+
+// Make sure options are usable
+fn check_options(options: &LsbOptions) -> Result<()> {
+    if !(1..=8).contains(&options.bits_per_channel) {
+        return Err(Error::Usage("Bits per channel must be between 1 and 8".to_string()));
+    }
+    if let Some(matrix_bits) = options.matrix_bits {
+        if !(2..=8).contains(&matrix_bits) {
+            return Err(Error::Usage("Matrix encoding bits must be between 2 and 8".to_string()));
+        }
+        if options.bits_per_channel != 1 {
+            return Err(Error::Usage("Matrix encoding only uses the lowest bit of each sample, bits per channel must be 1".to_string()));
+        }
+    }
+    Ok(())
+}
+
+// Make sure the image described by ihdr has the channels options select
+fn check_channels(ihdr: &Ihdr, options: &LsbOptions) -> Result<()> {
+    let alpha_only = Channels { red: false, green: false, blue: false, alpha: true };
+    if (options.translucent_only || options.channels == alpha_only) && !ihdr.colour_type().has_alpha() {
+        return Err(Error::Usage("Image has no alpha channel".to_string()));
+    }
+    if !selected_samples(ihdr.colour_type(), options.channels).contains(&true) {
+        return Err(Error::Usage(format!("Image has none of the selected channels {}", options.channels)));
+    }
+    Ok(())
+}
+
+// Number of message bits that fit in the given number of carrier samples
+fn carrier_bits(carriers: usize, options: &LsbOptions) -> usize {
+    match options.matrix_bits {
+        Some(matrix_bits) => carriers / group_size(matrix_bits) * matrix_bits as usize,
+        None => carriers.saturating_mul(options.bits_per_channel as usize)
+    }
+}
+
+// Number of samples in a Hamming code group carrying matrix_bits bits
+fn group_size(matrix_bits: u8) -> usize {
+    (1 << matrix_bits) - 1
+}
+
+// Hamming syndrome of the lowest bits of a group of samples, the XOR of the 1-based positions of the odd ones
+fn syndrome(pixels: &PixelBuffer, group: &[usize]) -> usize {
+    group
+        .iter()
+        .enumerate()
+        .filter(|(_, &index)| pixels.sample(index) & 1 == 1)
+        .fold(0, |syndrome, (position, _)| syndrome ^ (position + 1))
+}
+
+// Number of payload bytes that fit in the given number of carrier samples
+fn available_bytes(carriers: usize, options: &LsbOptions) -> usize {
+    prefixed_capacity(carrier_bits(carriers, options))
+}
+
+// Number of payload bytes that fit in the given number of bits behind the length prefix
+pub fn prefixed_capacity(bits: usize) -> usize {
+    (bits / 8).saturating_sub(LENGTH_PREFIX_SIZE)
+}
+
+// Bits of payload behind its big-endian length prefix, most significant first
+pub fn prefixed_bits(payload: &[u8]) -> Result<Vec<u8>> {
+    let length = u32::try_from(payload.len()).map_err(|_| "Payload is too large")?;
+    Ok([&length.to_be_bytes()[..], payload]
+        .concat()
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+        .collect())
+}
+
+// Read a payload framed by prefixed_bits, rejecting lengths beyond the available bytes as there is no message then
+pub fn read_prefixed(mut bits: impl Iterator<Item = u8>, available: usize) -> Result<Vec<u8>> {
+    let mut next_byte = || -> Option<u8> {
+        (0..8).try_fold(0u8, |byte, _| bits.next().map(|bit| (byte << 1) | bit))
+    };
+
+    let mut length_bytes = [0; LENGTH_PREFIX_SIZE];
+    for byte in length_bytes.iter_mut() {
+        *byte = next_byte().ok_or_else(|| Error::InvalidData("Image is too small to hold a hidden message".to_string()))?;
+    }
+    let length = u32::from_be_bytes(length_bytes) as usize;
+
+    let not_found = || Error::InvalidData("No hidden message found in image pixels".to_string());
+    if length > available {
+        return Err(not_found());
+    }
+
+    (0..length).map(|_| next_byte().ok_or_else(not_found)).collect()
+}
+
+// Maximum payload size in bytes that can be hidden in the pixels of PNG, computed from IHDR without decoding the pixels unless only translucent ones are used
+pub fn capacity(png: &Png, options: &LsbOptions) -> Result<usize> {
+    check_options(options)?;
+    let ihdr = png.ihdr()?;
+    check_image(&ihdr)?;
+    check_channels(&ihdr, options)?;
+    if options.translucent_only {
+        return Ok(available_bytes(carrier_indices(&png.pixels()?, options).len(), options));
+    }
+    let selected = selected_samples(ihdr.colour_type(), options.channels).into_iter().filter(|&selected| selected).count();
+    let carriers = (ihdr.width() as usize)
+        .checked_mul(ihdr.height() as usize)
+        .and_then(|pixels| pixels.checked_mul(selected))
+        .ok_or_else(|| Error::InvalidData(format!("Image of {}x{} pixels is too large", ihdr.width(), ihdr.height())))?;
+    Ok(available_bytes(carriers, options))
+}
+
+// Hide payload in the least significant bits of the selected channels of every pixel
+pub fn embed(png: &mut Png, payload: &[u8], options: &LsbOptions) -> Result<Changes> {
+    check_options(options)?;
+    let mut pixels = carrier_pixels(png, options)?;
+    let indices = carrier_indices(&pixels, options);
+    let bits_per_channel = options.bits_per_channel as usize;
+
+    let available = available_bytes(indices.len(), options);
+    if payload.len() > available {
+        return Err(format!("Payload of {} bytes does not fit in image, capacity is {} bytes", payload.len(), available).into());
+    }
+
+    let message_bits: Vec<u16> = prefixed_bits(payload)?.into_iter().map(u16::from).collect();
+
+    let carriers = match options.matrix_bits {
+        Some(matrix_bits) => message_bits.len().div_ceil(matrix_bits as usize) * group_size(matrix_bits),
+        None => message_bits.len().div_ceil(bits_per_channel)
+    };
+    let before: Vec<u16> = indices[..carriers].iter().map(|&index| pixels.sample(index)).collect();
+
+    match options.matrix_bits {
+        // every group of 2^k - 1 samples takes k bits as its syndrome, flipping the lowest bit of at most one sample
+        Some(matrix_bits) => {
+            for (group, bits) in indices.chunks_exact(group_size(matrix_bits)).zip(message_bits.chunks(matrix_bits as usize)) {
+                let value = bits.iter().fold(0, |value, &bit| (value << 1) | bit as usize) << (matrix_bits as usize - bits.len());
+                let position = syndrome(&pixels, group) ^ value;
+                if position != 0 {
+                    let index = group[position - 1];
+                    pixels.set_sample(index, pixels.sample(index) ^ 1);
+                }
+            }
+        },
+        // bits are written most significant first, filling the low bits of each sample from the top
+        None => {
+            for (i, &bit) in message_bits.iter().enumerate() {
+                let index = indices[i / bits_per_channel];
+                let shift = bits_per_channel - 1 - i % bits_per_channel;
+                pixels.set_sample(index, (pixels.sample(index) & !(1 << shift)) | (bit << shift));
+            }
+        }
+    }
+
+    let changed = indices[..carriers].iter().zip(before).filter(|&(&index, value)| pixels.sample(index) != value).count();
+    png.set_pixels(&pixels)?;
+    Ok(Changes { bits: message_bits.len(), carriers, changed })
+}
+
+// Extract a payload hidden by embed
+pub fn extract(png: &Png, options: &LsbOptions) -> Result<Vec<u8>> {
+    check_options(options)?;
+    let pixels = carrier_pixels(png, options)?;
+    let indices = carrier_indices(&pixels, options);
+    let bits_per_channel = options.bits_per_channel;
+
+    let bits: Box<dyn Iterator<Item = u8>> = match options.matrix_bits {
+        Some(matrix_bits) => Box::new(
+            indices
+                .chunks_exact(group_size(matrix_bits))
+                .map(|group| syndrome(&pixels, group))
+                .flat_map(move |syndrome| (0..matrix_bits).rev().map(move |shift| ((syndrome >> shift) & 1) as u8))
+        ),
+        None => Box::new(
+            indices
+                .iter()
+                .flat_map(|&index| (0..bits_per_channel).rev().map(move |shift| (index, shift)))
+                .map(|(index, shift)| ((pixels.sample(index) >> shift) & 1) as u8)
+        )
+    };
+    read_prefixed(bits, available_bytes(indices.len(), options))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::InterlaceMethod;
+
+    // Build a small non-interlaced PNG with the given colour type & bit depth
+    pub fn testing_png(colour_type: ColourType, bit_depth: u8) -> Png {
+        let ihdr = Ihdr::new(16, 12, bit_depth, colour_type, InterlaceMethod::None).unwrap();
+
+        let chunks = vec![
+            ihdr.to_chunk(),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ];
+        let mut png = Png::from_chunks(chunks);
+
+        let mut pixels = PixelBuffer::new(16, 12, colour_type, bit_depth).unwrap();
+        for index in 0..pixels.len() {
+            pixels.set_sample(index, (index * 31 % 256) as u16);
+        }
+        png.set_pixels(&pixels).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_prefixed_framing() {
+        let bits = prefixed_bits(b"Secret").unwrap();
+        assert_eq!(bits.len(), (LENGTH_PREFIX_SIZE + 6) * 8);
+        assert_eq!(prefixed_capacity(bits.len()), 6);
+        assert_eq!(read_prefixed(bits.iter().copied().chain([1, 0, 1]), 6).unwrap(), b"Secret");
+
+        // too few bits for the prefix, a length beyond what is available & a message cut short
+        assert!(matches!(read_prefixed(bits[..20].iter().copied(), 6), Err(Error::InvalidData(_))));
+        assert!(matches!(read_prefixed(bits.iter().copied(), 5), Err(Error::InvalidData(_))));
+        assert!(matches!(read_prefixed(bits[..bits.len() - 1].iter().copied(), 6), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_embed_extract_roundtrip() {
+        let images = [
+            (ColourType::Greyscale, 8),
+            (ColourType::Truecolour, 8),
+            (ColourType::GreyscaleAlpha, 8),
+            (ColourType::TruecolourAlpha, 8),
+            (ColourType::Truecolour, 16),
+            (ColourType::TruecolourAlpha, 16)
+        ];
+        for (colour_type, bit_depth) in images {
+            let mut png = testing_png(colour_type, bit_depth);
+            let options = LsbOptions::default();
+            embed(&mut png, b"Secret", &options).unwrap();
+            assert_eq!(extract(&png, &options).unwrap(), b"Secret");
+        }
+    }
+
+    #[test]
+    fn test_interlaced_roundtrip() {
+        let mut png = testing_png(ColourType::Truecolour, 8);
+        png.set_interlace_method(InterlaceMethod::Adam7).unwrap();
+        let options = LsbOptions::default();
+        embed(&mut png, b"Secret", &options).unwrap();
+
+        let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(png.ihdr().unwrap().interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(extract(&png, &options).unwrap(), b"Secret");
+    }
+
+    #[test]
+    fn test_translucent_only_roundtrip() {
+        let alpha = Channels::from_str("a").unwrap();
+        for (colour_type, bit_depth) in [(ColourType::TruecolourAlpha, 8), (ColourType::GreyscaleAlpha, 16)] {
+            // every other pixel is opaque, the others have alphas just below opaque
+            let mut png = testing_png(colour_type, bit_depth);
+            let mut pixels = png.pixels().unwrap();
+            let channel_count = colour_type.channel_count();
+            for pixel in 0..pixels.len() / channel_count {
+                let alpha_index = pixel * channel_count + channel_count - 1;
+                let value = if pixel % 2 == 0 { pixels.max_sample_value() } else { pixels.max_sample_value() - 2 - (pixel % 3) as u16 };
+                pixels.set_sample(alpha_index, value);
+            }
+            png.set_pixels(&pixels).unwrap();
+
+            for bits in [1, 2] {
+                let options = LsbOptions { channels: alpha, bits_per_channel: bits, translucent_only: true, ..LsbOptions::default() };
+                // bits above the low ones tell translucent from opaque, alphas of 254 & 253 only count with a single bit
+                let translucent = (0..pixels.len() / channel_count)
+                    .filter(|pixel| pixel % 2 == 1 && (bits == 1 || pixel % 3 == 2))
+                    .count();
+                assert_eq!(capacity(&png, &options).unwrap(), translucent * bits as usize / 8 - 4);
+
+                let mut embedded = Png::try_from(png.as_bytes().as_ref()).unwrap();
+                embed(&mut embedded, b"Hi", &options).unwrap();
+                let embedded = Png::try_from(embedded.as_bytes().as_ref()).unwrap();
+                assert_eq!(extract(&embedded, &options).unwrap(), b"Hi");
+
+                // only alpha samples of translucent pixels change
+                let after = embedded.pixels().unwrap();
+                for index in 0..pixels.len() {
+                    if pixels.sample(index) != after.sample(index) {
+                        assert_eq!(index % channel_count, channel_count - 1);
+                        assert_ne!(pixels.sample(index), pixels.max_sample_value());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_translucent_only_requires_alpha() {
+        let options = LsbOptions { translucent_only: true, ..LsbOptions::default() };
+        assert!(capacity(&testing_png(ColourType::Truecolour, 8), &options).is_err());
+
+        let alpha = LsbOptions { channels: Channels::from_str("a").unwrap(), ..LsbOptions::default() };
+        assert!(capacity(&testing_png(ColourType::Greyscale, 8), &alpha).is_err());
+    }
+
+    #[test]
+    fn test_matrix_roundtrip() {
+        for (matrix_bits, scatter_key) in [(2, None), (3, Some([5; 32])), (4, None)] {
+            let mut png = testing_png(ColourType::Truecolour, 8);
+            let before = png.pixels().unwrap();
+            let options = LsbOptions { matrix_bits: Some(matrix_bits), scatter_key, ..LsbOptions::default() };
+            let changes = embed(&mut png, b"Secret", &options).unwrap();
+
+            let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+            assert_eq!(extract(&png, &options).unwrap(), b"Secret");
+
+            // at most one lowest bit flips in every group
+            let groups = changes.bits.div_ceil(matrix_bits as usize);
+            assert_eq!(changes.carriers, groups * group_size(matrix_bits));
+            assert!(changes.changed <= groups);
+            let after = png.pixels().unwrap();
+            let changed = (0..before.len()).filter(|&index| before.sample(index) != after.sample(index)).count();
+            assert_eq!(changed, changes.changed);
+            assert!((0..before.len()).all(|index| before.sample(index) >> 1 == after.sample(index) >> 1));
+        }
+    }
+
+    #[test]
+    fn test_matrix_changes_fewer_samples() {
+        let payload: Vec<u8> = (0..20u8).map(|i| i.wrapping_mul(73)).collect();
+        let plain = embed(&mut testing_png(ColourType::Truecolour, 8), &payload, &LsbOptions::default()).unwrap();
+        let options = LsbOptions { matrix_bits: Some(3), ..LsbOptions::default() };
+        let matrix = embed(&mut testing_png(ColourType::Truecolour, 8), &payload, &options).unwrap();
+
+        // about 1/2 against 7/8 of 1/3 changes per bit
+        assert_eq!(plain.bits, matrix.bits);
+        assert!(matrix.per_bit() < plain.per_bit());
+        assert!(matrix.per_bit() <= 1.0 / 3.0 + f64::EPSILON);
+        assert_eq!(capacity(&testing_png(ColourType::Truecolour, 8), &options).unwrap(), 16 * 12 * 3 / 7 * 3 / 8 - 4);
+    }
+
+    #[test]
+    fn test_matrix_options() {
+        let png = testing_png(ColourType::Truecolour, 8);
+        assert!(capacity(&png, &LsbOptions { matrix_bits: Some(1), ..LsbOptions::default() }).is_err());
+        assert!(capacity(&png, &LsbOptions { matrix_bits: Some(9), ..LsbOptions::default() }).is_err());
+        assert!(capacity(&png, &LsbOptions { matrix_bits: Some(3), bits_per_channel: 2, ..LsbOptions::default() }).is_err());
+    }
+
+    #[test]
+    fn test_embed_changes_only_low_bits() {
+        let before = testing_png(ColourType::TruecolourAlpha, 8).pixels().unwrap();
+        let mut png = testing_png(ColourType::TruecolourAlpha, 8);
+        let options = LsbOptions { channels: Channels::from_str("rgb").unwrap(), bits_per_channel: 2, ..LsbOptions::default() };
+        embed(&mut png, b"This is where your secret message will be!", &options).unwrap();
+        let after = png.pixels().unwrap();
+
+        assert_ne!(before, after);
+        for index in 0..before.len() {
+            assert_eq!(before.sample(index) & !0b11, after.sample(index) & !0b11);
+            // alpha channel is untouched
+            if index % 4 == 3 {
+                assert_eq!(before.sample(index), after.sample(index));
+            }
+        }
+    }
+
+    #[test]
+    fn test_scattered_roundtrip() {
+        let mut png = testing_png(ColourType::Truecolour, 8);
+        let options = LsbOptions { scatter_key: Some([7; 32]), ..LsbOptions::default() };
+        embed(&mut png, b"Secret", &options).unwrap();
+        assert_eq!(extract(&png, &options).unwrap(), b"Secret");
+
+        // without the key the bits are read in the wrong order
+        let wrong_key = LsbOptions { scatter_key: Some([8; 32]), ..LsbOptions::default() };
+        assert_ne!(extract(&png, &wrong_key).ok(), Some(b"Secret".to_vec()));
+        assert_ne!(extract(&png, &LsbOptions::default()).ok(), Some(b"Secret".to_vec()));
+    }
+
+    #[test]
+    fn test_scattered_bits_spread_over_image() {
+        let before = testing_png(ColourType::Truecolour, 8).pixels().unwrap();
+        let mut png = testing_png(ColourType::Truecolour, 8);
+        let options = LsbOptions { scatter_key: Some([7; 32]), ..LsbOptions::default() };
+        embed(&mut png, b"Secret", &options).unwrap();
+        let after = png.pixels().unwrap();
+
+        // 80 bits sequentially would only reach the first 80 samples
+        let changed: Vec<usize> = (0..before.len()).filter(|&index| before.sample(index) != after.sample(index)).collect();
+        assert!(changed.iter().any(|&index| index >= before.len() / 2));
+    }
+
+    #[test]
+    fn test_shuffle_is_stable() {
+        // the order is part of the format, changing it would break decoding of existing images
+        let mut indices: Vec<usize> = (0..10).collect();
+        shuffle(&mut indices, [0; 32]);
+        assert_eq!(indices, vec![9, 7, 3, 6, 1, 4, 8, 5, 2, 0]);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png(ColourType::Truecolour, 8);
+        // 16 * 12 pixels with 3 channels of 1 bit each
+        assert_eq!(capacity(&png, &LsbOptions::default()).unwrap(), 16 * 12 * 3 / 8 - 4);
+
+        let options = LsbOptions { channels: Channels::default(), bits_per_channel: 3, ..LsbOptions::default() };
+        assert_eq!(capacity(&png, &options).unwrap(), 16 * 12 * 9 / 8 - 4);
+    }
+
+    #[test]
+    fn test_payload_too_large() {
+        let mut png = testing_png(ColourType::Greyscale, 8);
+        let payload = vec![0; capacity(&png, &LsbOptions::default()).unwrap() + 1];
+        assert!(embed(&mut png, &payload, &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_image() {
+        let png = testing_png(ColourType::Indexed, 8);
+        assert!(capacity(&png, &LsbOptions::default()).is_err());
+
+        let png = testing_png(ColourType::Greyscale, 4);
+        assert!(capacity(&png, &LsbOptions::default()).is_err());
+    }
+
+    #[test]
+    fn test_channels_from_str() {
+        let channels = Channels::from_str("ga").unwrap();
+        assert_eq!(channels, Channels { red: false, green: true, blue: false, alpha: true });
+        assert_eq!(channels.to_string(), "ga");
+        assert!(Channels::from_str("rgx").is_err());
+        assert!(Channels::from_str("").is_err());
+    }
+}
@@ -1,23 +1,56 @@
 use clap::Parser;
-use commands::{encode, decode, remove, print};
+use commands::{encode, decode, remove, print, keygen, verify, lint, meta, capacity, detect};
 
 mod args;
+mod capacity;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod compression;
+mod crypto;
+mod detect;
+mod disguise;
+mod ecc;
+mod envelope;
+mod error;
+mod filter;
+mod fragment;
+mod ihdr;
+mod interlace;
+mod keys;
+mod lint;
+mod lsb;
+mod palette;
+mod payload;
+mod pixels;
 mod png;
+mod signature;
+#[cfg(test)]
+mod test_support;
+mod text;
+mod trailer;
 
-pub type Error = Box<dyn std::error::Error>;
+pub use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 fn main() {
     let args = args::ImgcryptArgs::parse();
 
-    match args.command {
+    let result = match args.command {
         args::Command::Encode(cmd_args) => encode(cmd_args),
         args::Command::Decode(cmd_args) => decode(cmd_args),
         args::Command::Remove(cmd_args) => remove(cmd_args),
         args::Command::Print(cmd_args) => print(cmd_args),
+        args::Command::Keygen(cmd_args) => keygen(cmd_args),
+        args::Command::Verify(cmd_args) => verify(cmd_args),
+        args::Command::Lint(cmd_args) => lint(cmd_args),
+        args::Command::Meta(cmd_args) => meta(cmd_args),
+        args::Command::Capacity(cmd_args) => capacity(cmd_args),
+        args::Command::Detect(cmd_args) => detect(cmd_args),
     };
 
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(e.exit_code());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,236 @@
+use crate::{Error, Result};
+use crate::lsb;
+use crate::pixels::{ColourType, PixelBuffer};
+use crate::png::Png;
+
+const MAX_PAIR_DISTANCE: f64 = 6.0;     // largest CIE76 colour difference of entries paired to carry bits, about twice the smallest noticeable one
+
+// Colours of an indexed image, from PLTE with the opacity of each entry from tRNS
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    entries: Vec<[u8; 4]>
+}
+
+impl Palette {
+    // Parse the PLTE & tRNS chunks of PNG
+    pub fn from_png(png: &Png) -> Result<Palette> {
+        let plte = png.chunk_by_type("PLTE").ok_or_else(|| Error::InvalidData("Indexed-colour image has no PLTE chunk".to_string()))?;
+        let colours = plte.data();
+        if colours.is_empty() || colours.len() % 3 != 0 || colours.len() > 256 * 3 {
+            return Err(Error::InvalidData(format!("PLTE chunk of {} bytes does not hold 1 to 256 colours", colours.len())));
+        }
+
+        // entries without an alpha value in tRNS are opaque
+        let alpha = png.chunk_by_type("tRNS").map_or(&[][..], |chunk| chunk.data());
+        let entries = colours
+            .chunks(3)
+            .enumerate()
+            .map(|(index, rgb)| [rgb[0], rgb[1], rgb[2], alpha.get(index).copied().unwrap_or(255)])
+            .collect();
+        Ok(Palette { entries })
+    }
+
+    // Number of colours
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Partner of every entry, pairing the closest colours first so each pixel can swap to a near-identical one
+    fn pairs(&self) -> Vec<Option<usize>> {
+        let lab: Vec<[f64; 3]> = self.entries.iter().map(|entry| lab(*entry)).collect();
+        let mut candidates = Vec::new();
+        for i in 0..self.len() {
+            for j in i + 1..self.len() {
+                let alpha = (self.entries[i][3] as f64 - self.entries[j][3] as f64) * 100.0 / 255.0;
+                let distance = (0..3).map(|k| (lab[i][k] - lab[j][k]).powi(2)).sum::<f64>() + alpha.powi(2);
+                if distance <= MAX_PAIR_DISTANCE.powi(2) {
+                    candidates.push((distance, i, j));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+        let mut partners = vec![None; self.len()];
+        for (_, i, j) in candidates {
+            if partners[i].is_none() && partners[j].is_none() {
+                partners[i] = Some(j);
+                partners[j] = Some(i);
+            }
+        }
+        partners
+    }
+}
+
+// CIE L*a*b* coordinates of an sRGB colour under the D65 white point
+fn lab(entry: [u8; 4]) -> [f64; 3] {
+    let linear = |value: u8| {
+        let value = value as f64 / 255.0;
+        if value <= 0.04045 { value / 12.92 } else { ((value + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (linear(entry[0]), linear(entry[1]), linear(entry[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| if t > 216.0 / 24389.0 { t.cbrt() } else { (24389.0 / 27.0 * t + 16.0) / 116.0 };
+    [116.0 * f(y) - 16.0, 500.0 * (f(x) - f(y)), 200.0 * (f(y) - f(z))]
+}
+
+// Decode the palette & pixel indices of PNG, making sure it is an indexed-colour image
+fn indexed_pixels(png: &Png) -> Result<(Palette, PixelBuffer)> {
+    if png.ihdr()?.colour_type() != ColourType::Indexed {
+        return Err(Error::Usage("Palette embedding requires an indexed-colour image".to_string()));
+    }
+    let palette = Palette::from_png(png)?;
+    let pixels = png.pixels()?;
+    if (0..pixels.len()).any(|index| pixels.sample(index) as usize >= palette.len()) {
+        return Err(Error::InvalidData("Image uses colours missing from its palette".to_string()));
+    }
+    Ok((palette, pixels))
+}
+
+// Pixels whose colour has a partner, each carrying a bit in which of the two it uses, in the order they carry payload bits
+fn carrier_indices(pixels: &PixelBuffer, partners: &[Option<usize>], scatter_key: Option<[u8; 32]>) -> Vec<usize> {
+    let mut indices: Vec<usize> = (0..pixels.len()).filter(|&index| partners[pixels.sample(index) as usize].is_some()).collect();
+    if let Some(key) = scatter_key {
+        lsb::shuffle(&mut indices, key);
+    }
+    indices
+}
+
+// Maximum payload size in bytes that can be hidden in the pixels of PNG
+pub fn capacity(png: &Png) -> Result<usize> {
+    let (palette, pixels) = indexed_pixels(png)?;
+    Ok(lsb::prefixed_capacity(carrier_indices(&pixels, &palette.pairs(), None).len()))
+}
+
+// Hide payload by switching pixels between the two colours of near-identical palette pairs, leaving the palette as it is
+pub fn embed(png: &mut Png, payload: &[u8], scatter_key: Option<[u8; 32]>) -> Result<()> {
+    let (palette, mut pixels) = indexed_pixels(png)?;
+    let partners = palette.pairs();
+    let indices = carrier_indices(&pixels, &partners, scatter_key);
+    if indices.is_empty() {
+        return Err("Palette has no pairs of near-identical colours used by the image".into());
+    }
+
+    let available = lsb::prefixed_capacity(indices.len());
+    if payload.len() > available {
+        return Err(format!("Payload of {} bytes does not fit in image, capacity is {} bytes", payload.len(), available).into());
+    }
+
+    // the lower index of each pair stands for 0 & the higher for 1
+    for (&index, bit) in indices.iter().zip(lsb::prefixed_bits(payload)?) {
+        let colour = pixels.sample(index) as usize;
+        let partner = partners[colour].expect("carrier pixels have a partner colour");
+        if ((colour > partner) as u8) != bit {
+            pixels.set_sample(index, partner as u16);
+        }
+    }
+
+    png.set_pixels(&pixels)
+}
+
+// Extract a payload hidden by embed
+pub fn extract(png: &Png, scatter_key: Option<[u8; 32]>) -> Result<Vec<u8>> {
+    let (palette, pixels) = indexed_pixels(png)?;
+    let partners = palette.pairs();
+    let indices = carrier_indices(&pixels, &partners, scatter_key);
+
+    let bits = indices.iter().map(|&index| {
+        let colour = pixels.sample(index) as usize;
+        partners[colour].is_some_and(|partner| colour > partner) as u8
+    });
+    lsb::read_prefixed(bits, lsb::prefixed_capacity(indices.len()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::ihdr::{Ihdr, InterlaceMethod};
+
+    // 32x24 indexed image using a palette of 4 near-identical pairs & 1 unpaired colour
+    fn testing_png() -> Png {
+        let palette: [[u8; 3]; 9] = [
+            [200, 30, 30], [20, 20, 200], [201, 31, 30], [250, 250, 250], [20, 21, 202],
+            [0, 0, 0], [251, 250, 250], [1, 1, 1], [0, 255, 0]
+        ];
+        let ihdr = Ihdr::new(32, 24, 8, ColourType::Indexed, InterlaceMethod::None).unwrap();
+        let chunk = |chunk_type: &str, data: Vec<u8>| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data);
+        let mut png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            chunk("PLTE", palette.concat()),
+            chunk("IDAT", Vec::new()),
+            chunk("IEND", Vec::new())
+        ]);
+
+        let mut pixels = PixelBuffer::new(32, 24, ColourType::Indexed, 8).unwrap();
+        for index in 0..pixels.len() {
+            pixels.set_sample(index, (index * 7 % 9) as u16);
+        }
+        png.set_pixels(&pixels).unwrap();
+        png
+    }
+
+    #[test]
+    fn test_pairs() {
+        let palette = Palette::from_png(&testing_png()).unwrap();
+        assert_eq!(palette.pairs(), vec![Some(2), Some(4), Some(0), Some(6), Some(1), Some(7), Some(3), Some(5), None]);
+    }
+
+    #[test]
+    fn test_transparency_splits_pairs() {
+        let mut png = testing_png();
+        // first colour becomes transparent, so it no longer matches its opaque neighbour
+        png.insert_chunk(Chunk::new(ChunkType::from_str("tRNS").unwrap(), vec![0]));
+        let palette = Palette::from_png(&png).unwrap();
+        assert_eq!(palette.entries[0], [200, 30, 30, 0]);
+        assert_eq!(palette.pairs()[0], None);
+        assert_eq!(palette.pairs()[2], None);
+    }
+
+    #[test]
+    fn test_embed_extract_roundtrip() {
+        for scatter_key in [None, Some([3; 32])] {
+            let mut png = testing_png();
+            let before = png.pixels().unwrap();
+            embed(&mut png, b"Secret", scatter_key).unwrap();
+
+            let png = Png::try_from(png.as_bytes().as_ref()).unwrap();
+            assert_eq!(extract(&png, scatter_key).unwrap(), b"Secret");
+
+            // pixels only ever swap to their partner colour & the palette is unchanged
+            let partners = Palette::from_png(&png).unwrap().pairs();
+            let after = png.pixels().unwrap();
+            for index in 0..before.len() {
+                let (old, new) = (before.sample(index), after.sample(index));
+                assert!(old == new || partners[old as usize] == Some(new as usize));
+            }
+            assert_eq!(png.chunk_by_type("PLTE").unwrap().data(), testing_png().chunk_by_type("PLTE").unwrap().data());
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        let png = testing_png();
+        // 8 of every 9 pixels use a paired colour
+        let carriers = (0..32 * 24).filter(|index| index * 7 % 9 != 8).count();
+        assert_eq!(capacity(&png).unwrap(), carriers / 8 - lsb::LENGTH_PREFIX_SIZE);
+
+        let payload = vec![0; capacity(&png).unwrap() + 1];
+        assert!(embed(&mut testing_png(), &payload, None).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_image() {
+        let ihdr = Ihdr::new(4, 4, 8, ColourType::Truecolour, InterlaceMethod::None).unwrap();
+        assert!(capacity(&Png::from_chunks(vec![ihdr.to_chunk()])).is_err());
+
+        let mut png = testing_png();
+        png.remove_chunk("PLTE").unwrap();
+        assert!(capacity(&png).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{Error, Result};
+
+const MAGIC: [u8; 4] = *b"IPLD";
+const FORMAT_VERSION: u8 = 1;
+pub const TEXT_CONTENT_TYPE: &str = "text/plain; charset=utf-8";
+pub const BINARY_CONTENT_TYPE: &str = "application/octet-stream";
+
+// Content types recognised from the first bytes of a file
+const SIGNATURES: [(&[u8], &str); 7] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed")
+];
+
+// Hidden data together with a header describing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Payload {
+    pub filename: Option<String>,   // name of the original file, None for messages given on the command line
+    pub content_type: String,       // MIME type of data
+    pub data: Vec<u8>
+}
+
+impl Payload {
+    // Creates payload for a text message
+    pub fn text(message: &str) -> Payload {
+        Payload {
+            filename: None,
+            content_type: TEXT_CONTENT_TYPE.to_string(),
+            data: message.as_bytes().to_vec()
+        }
+    }
+
+    // Creates payload for the contents of a file, guessing its content type
+    pub fn file(filename: Option<String>, data: Vec<u8>) -> Payload {
+        Payload {
+            filename,
+            content_type: guess_content_type(&data).to_string(),
+            data
+        }
+    }
+
+    // Check if data is text that can be printed
+    pub fn is_text(&self) -> bool {
+        self.content_type.starts_with("text/") && std::str::from_utf8(&self.data).is_ok()
+    }
+
+    // Number of bytes as_bytes adds in front of data
+    pub fn header_length(&self) -> usize {
+        MAGIC.len() + 1 + 2 + self.filename.as_deref().map_or(0, str::len) + 1 + self.content_type.len() + 8
+    }
+
+    // Convert to bytes containing in order: magic, version, filename length & filename, content type length & content type, data size & data
+    pub fn as_bytes(&self) -> Result<Vec<u8>> {
+        let filename = self.filename.as_deref().unwrap_or("");
+        let filename_length = u16::try_from(filename.len()).map_err(|_| Error::Usage("Filename is too long".to_string()))?;
+        let content_type_length = u8::try_from(self.content_type.len()).map_err(|_| Error::Usage("Content type is too long".to_string()))?;
+
+        Ok(MAGIC
+            .iter()
+            .chain([FORMAT_VERSION].iter())
+            .chain(filename_length.to_be_bytes().iter())
+            .chain(filename.as_bytes().iter())
+            .chain([content_type_length].iter())
+            .chain(self.content_type.as_bytes().iter())
+            .chain((self.data.len() as u64).to_be_bytes().iter())
+            .chain(self.data.iter())
+            .copied()
+            .collect())
+    }
+
+    // Parse bytes written by as_bytes, data without a payload header is treated as a text message
+    pub fn from_bytes(bytes: &[u8]) -> Result<Payload> {
+        if !bytes.starts_with(&MAGIC) {
+            return Ok(Payload {
+                filename: None,
+                content_type: TEXT_CONTENT_TYPE.to_string(),
+                data: bytes.to_vec()
+            });
+        }
+
+        let malformed = || Error::InvalidData("Malformed payload header".to_string());
+        let mut rest = &bytes[MAGIC.len()..];
+        let mut take = |count: usize| -> Result<&[u8]> {
+            if rest.len() < count {
+                return Err(malformed());
+            }
+            let (taken, remaining) = rest.split_at(count);
+            rest = remaining;
+            Ok(taken)
+        };
+
+        let version = take(1)?[0];
+        if version != FORMAT_VERSION {
+            return Err(Error::InvalidData(format!("Unsupported payload format version {}", version)));
+        }
+
+        let filename_length = take(2)?;
+        let filename = String::from_utf8(take(u16::from_be_bytes([filename_length[0], filename_length[1]]) as usize)?.to_vec())?;
+        let content_type_length = take(1)?[0] as usize;
+        let content_type = String::from_utf8(take(content_type_length)?.to_vec())?;
+
+        let size: [u8; 8] = take(8)?.try_into().map_err(|_| malformed())?;
+        let size = usize::try_from(u64::from_be_bytes(size)).map_err(|_| malformed())?;
+        let data = take(size)?.to_vec();
+        if !rest.is_empty() {
+            return Err(malformed());
+        }
+
+        Ok(Payload {
+            filename: if filename.is_empty() { None } else { Some(filename) },
+            content_type,
+            data
+        })
+    }
+}
+
+// Guess the MIME type of data from its first bytes
+pub fn guess_content_type(data: &[u8]) -> &'static str {
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+        .unwrap_or(if std::str::from_utf8(data).is_ok() { TEXT_CONTENT_TYPE } else { BINARY_CONTENT_TYPE })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload_roundtrip() {
+        let payload = Payload::file(Some("key.bin".to_string()), vec![0, 159, 146, 150, 255]);
+        assert_eq!(payload.content_type, BINARY_CONTENT_TYPE);
+        assert_eq!(payload.as_bytes().unwrap().len(), payload.header_length() + 5);
+        assert_eq!(Payload::from_bytes(&payload.as_bytes().unwrap()).unwrap(), payload);
+
+        let payload = Payload::text("Secret");
+        assert_eq!(Payload::from_bytes(&payload.as_bytes().unwrap()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_payload_without_header() {
+        let payload = Payload::from_bytes(b"Plain message").unwrap();
+        assert_eq!(payload, Payload::text("Plain message"));
+        assert!(payload.is_text());
+    }
+
+    #[test]
+    fn test_malformed_payload() {
+        let bytes = Payload::file(Some("a.txt".to_string()), b"Hello".to_vec()).as_bytes().unwrap();
+        assert!(Payload::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Payload::from_bytes(&[&bytes[..], b"!"].concat()).is_err());
+
+        let mut bytes = bytes;
+        bytes[4] = 2;
+        assert!(Payload::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_guess_content_type() {
+        assert_eq!(guess_content_type(b"\x89PNG\r\n\x1a\n\0\0"), "image/png");
+        assert_eq!(guess_content_type(b"PK\x03\x04rest"), "application/zip");
+        assert_eq!(guess_content_type(b"Just text"), TEXT_CONTENT_TYPE);
+        assert_eq!(guess_content_type(&[0xff, 0x00]), BINARY_CONTENT_TYPE);
+    }
+}
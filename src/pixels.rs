@@ -0,0 +1,348 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::{Error, Result};
+use crate::filter::{self, FilterType};
+
+// Colour type of a PNG image, determining which samples make up a pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColourType {
+    Greyscale = 0,
+    Truecolour = 2,
+    Indexed = 3,
+    GreyscaleAlpha = 4,
+    TruecolourAlpha = 6
+}
+
+impl ColourType {
+    // Number of samples per pixel
+    pub fn channel_count(&self) -> usize {
+        match self {
+            ColourType::Greyscale | ColourType::Indexed => 1,
+            ColourType::GreyscaleAlpha => 2,
+            ColourType::Truecolour => 3,
+            ColourType::TruecolourAlpha => 4
+        }
+    }
+
+    // Check if pixels have an alpha sample, which is always the last sample
+    pub fn has_alpha(&self) -> bool {
+        matches!(self, ColourType::GreyscaleAlpha | ColourType::TruecolourAlpha)
+    }
+
+    // Bit depths allowed by the PNG specification for this colour type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            ColourType::Greyscale => &[1, 2, 4, 8, 16],
+            ColourType::Indexed => &[1, 2, 4, 8],
+            _ => &[8, 16]
+        }
+    }
+}
+
+impl TryFrom<u8> for ColourType {
+    type Error = &'static str;
+
+    fn try_from(byte: u8) -> std::result::Result<Self, Self::Error> {
+        match byte {
+            0 => Ok(ColourType::Greyscale),
+            2 => Ok(ColourType::Truecolour),
+            3 => Ok(ColourType::Indexed),
+            4 => Ok(ColourType::GreyscaleAlpha),
+            6 => Ok(ColourType::TruecolourAlpha),
+            _ => Err("Invalid colour type")
+        }
+    }
+}
+
+impl fmt::Display for ColourType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColourType::Greyscale => "greyscale",
+            ColourType::Truecolour => "truecolour",
+            ColourType::Indexed => "indexed-colour",
+            ColourType::GreyscaleAlpha => "greyscale with alpha",
+            ColourType::TruecolourAlpha => "truecolour with alpha"
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Unpacked samples, one value per sample in pixel order, row by row
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Samples {
+    Eight(Vec<u8>),     // bit depths 1, 2, 4 & 8, or palette indices
+    Sixteen(Vec<u16>)   // bit depth 16
+}
+
+// Decoded image pixels
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    width: u32,
+    height: u32,
+    colour_type: ColourType,
+    bit_depth: u8,
+    samples: Samples
+}
+
+impl PixelBuffer {
+    // Creates a pixel buffer with every sample set to zero
+    pub fn new(width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<PixelBuffer> {
+        if !colour_type.allowed_bit_depths().contains(&bit_depth) {
+            return Err(Error::InvalidData(format!("Bit depth {} is not allowed for {} images", bit_depth, colour_type)));
+        }
+
+        let count = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(colour_type.channel_count()))
+            .ok_or_else(|| too_large(width, height))?;
+        let samples = if bit_depth == 16 { Samples::Sixteen(vec![0; count]) } else { Samples::Eight(vec![0; count]) };
+
+        Ok(PixelBuffer { width, height, colour_type, bit_depth, samples })
+    }
+
+    // Width in pixels
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    // Height in pixels
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // Colour type of pixels
+    pub fn colour_type(&self) -> ColourType {
+        self.colour_type
+    }
+
+    // Number of bits per sample
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    // Total number of samples
+    pub fn len(&self) -> usize {
+        match &self.samples {
+            Samples::Eight(samples) => samples.len(),
+            Samples::Sixteen(samples) => samples.len()
+        }
+    }
+
+    // Largest value a sample can hold
+    pub fn max_sample_value(&self) -> u16 {
+        ((1u32 << self.bit_depth) - 1) as u16
+    }
+
+    // Index of a sample given pixel coordinates & channel
+    pub fn index(&self, x: u32, y: u32, channel: usize) -> usize {
+        (y as usize * self.width as usize + x as usize) * self.colour_type.channel_count() + channel
+    }
+
+    // Sample at index
+    pub fn sample(&self, index: usize) -> u16 {
+        match &self.samples {
+            Samples::Eight(samples) => samples[index] as u16,
+            Samples::Sixteen(samples) => samples[index]
+        }
+    }
+
+    // Set sample at index, discarding bits beyond the bit depth
+    pub fn set_sample(&mut self, index: usize, value: u16) {
+        let value = value & self.max_sample_value();
+        match &mut self.samples {
+            Samples::Eight(samples) => samples[index] = value as u8,
+            Samples::Sixteen(samples) => samples[index] = value
+        }
+    }
+
+    // All samples of pixel at coordinates
+    #[cfg(test)]
+    pub fn pixel(&self, x: u32, y: u32) -> Vec<u16> {
+        let start = self.index(x, y, 0);
+        (start..start + self.colour_type.channel_count()).map(|index| self.sample(index)).collect()
+    }
+
+    // Number of bytes in a packed scanline
+    pub fn row_length(&self) -> usize {
+        (self.width as usize * self.colour_type.channel_count() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    // Number of bytes per complete pixel used by filters, rounded up to 1
+    pub fn bytes_per_pixel(&self) -> usize {
+        (self.colour_type.channel_count() * self.bit_depth as usize).div_ceil(8)
+    }
+
+    // Unpack raw, unfiltered scanlines into the samples of this buffer
+    fn unpack(&mut self, raw: &[u8]) {
+        let row_length = self.row_length();
+        let row_samples = self.width as usize * self.colour_type.channel_count();
+        let bit_depth = self.bit_depth as usize;
+
+        for y in 0..self.height as usize {
+            let row = &raw[y * row_length..(y + 1) * row_length];
+            for i in 0..row_samples {
+                let value = match bit_depth {
+                    16 => u16::from_be_bytes([row[2 * i], row[2 * i + 1]]),
+                    8 => row[i] as u16,
+                    _ => {
+                        // samples narrower than a byte are packed most significant bits first
+                        let bit = i * bit_depth;
+                        let shift = 8 - bit_depth - bit % 8;
+                        ((row[bit / 8] >> shift) as u16) & self.max_sample_value()
+                    }
+                };
+                self.set_sample(y * row_samples + i, value);
+            }
+        }
+    }
+
+    // Pack samples into raw, unfiltered scanlines
+    pub fn to_scanlines(&self) -> Vec<u8> {
+        let row_length = self.row_length();
+        let row_samples = self.width as usize * self.colour_type.channel_count();
+        let bit_depth = self.bit_depth as usize;
+        let mut raw = vec![0; row_length * self.height as usize];
+
+        for y in 0..self.height as usize {
+            let row = &mut raw[y * row_length..(y + 1) * row_length];
+            for i in 0..row_samples {
+                let value = self.sample(y * row_samples + i);
+                match bit_depth {
+                    16 => row[2 * i..2 * i + 2].copy_from_slice(&value.to_be_bytes()),
+                    8 => row[i] = value as u8,
+                    _ => {
+                        let bit = i * bit_depth;
+                        row[bit / 8] |= (value as u8) << (8 - bit_depth - bit % 8);
+                    }
+                }
+            }
+        }
+
+        raw
+    }
+
+    // Decode decompressed, filtered image data into a pixel buffer
+    pub fn decode(data: &[u8], width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<PixelBuffer> {
+        // the data has to be there before the dimensions in an untrusted header are worth allocating for
+        if data.len() < filtered_length(width, height, colour_type, bit_depth)? {
+            return Err(Error::InvalidData("Image data is shorter than the image dimensions require".to_string()));
+        }
+        let mut pixels = PixelBuffer::new(width, height, colour_type, bit_depth)?;
+        let raw = filter::unfilter(data, pixels.row_length(), height as usize, pixels.bytes_per_pixel())?;
+        pixels.unpack(&raw);
+        Ok(pixels)
+    }
+
+    // Encode pixels into filtered image data ready to be compressed
+    pub fn encode(&self) -> Vec<u8> {
+        let raw = self.to_scanlines();
+
+        // the specification recommends no filtering for indexed-colour & sub-byte images
+        if self.colour_type == ColourType::Indexed || self.bit_depth < 8 {
+            filter::filter_with_type(&raw, self.row_length(), self.bytes_per_pixel(), FilterType::None)
+        } else {
+            filter::filter(&raw, self.row_length(), self.bytes_per_pixel())
+        }
+    }
+}
+
+// Number of bytes of filtered scanlines an image of the given geometry needs, without allocating it
+pub fn filtered_length(width: u32, height: u32, colour_type: ColourType, bit_depth: u8) -> Result<usize> {
+    (width as usize)
+        .checked_mul(colour_type.channel_count() * bit_depth as usize)
+        .map(|bits| bits.div_ceil(8) + 1)
+        .and_then(|row_length| row_length.checked_mul(height as usize))
+        .ok_or_else(|| too_large(width, height))
+}
+
+// Error for image dimensions whose sample count overflows
+fn too_large(width: u32, height: u32) -> Error {
+    Error::InvalidData(format!("Image of {}x{} pixels is too large", width, height))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_COLOUR_TYPES: [ColourType; 5] = [
+        ColourType::Greyscale,
+        ColourType::Truecolour,
+        ColourType::Indexed,
+        ColourType::GreyscaleAlpha,
+        ColourType::TruecolourAlpha
+    ];
+
+    fn testing_pixels(colour_type: ColourType, bit_depth: u8) -> PixelBuffer {
+        let mut pixels = PixelBuffer::new(7, 5, colour_type, bit_depth).unwrap();
+        for index in 0..pixels.len() {
+            pixels.set_sample(index, (index as u16).wrapping_mul(40503));
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        for colour_type in ALL_COLOUR_TYPES {
+            for &bit_depth in colour_type.allowed_bit_depths() {
+                let pixels = testing_pixels(colour_type, bit_depth);
+                let encoded = pixels.encode();
+                assert_eq!(encoded.len(), (pixels.row_length() + 1) * 5);
+
+                let decoded = PixelBuffer::decode(&encoded, 7, 5, colour_type, bit_depth).unwrap();
+                assert_eq!(decoded, pixels);
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_sub_byte_samples() {
+        // single row of 4 two-bit greyscale samples & 4 bits of padding
+        let data = [0, 0b11_10_01_00, 0b1000_0000];
+        let pixels = PixelBuffer::decode(&data, 6, 1, ColourType::Greyscale, 2).unwrap();
+        let samples: Vec<u16> = (0..6).map(|index| pixels.sample(index)).collect();
+        assert_eq!(samples, vec![3, 2, 1, 0, 2, 0]);
+    }
+
+    #[test]
+    fn test_unpack_sixteen_bit_samples() {
+        let data = [0, 0x12, 0x34, 0xab, 0xcd];
+        let pixels = PixelBuffer::decode(&data, 1, 1, ColourType::GreyscaleAlpha, 16).unwrap();
+        assert_eq!(pixels.pixel(0, 0), vec![0x1234, 0xabcd]);
+    }
+
+    #[test]
+    fn test_row_length() {
+        assert_eq!(PixelBuffer::new(7, 1, ColourType::Greyscale, 1).unwrap().row_length(), 1);
+        assert_eq!(PixelBuffer::new(9, 1, ColourType::Indexed, 4).unwrap().row_length(), 5);
+        assert_eq!(PixelBuffer::new(3, 1, ColourType::TruecolourAlpha, 16).unwrap().row_length(), 24);
+        assert_eq!(PixelBuffer::new(3, 1, ColourType::Greyscale, 2).unwrap().bytes_per_pixel(), 1);
+    }
+
+    #[test]
+    fn test_oversized_dimensions() {
+        // sample counts beyond the address space fail instead of overflowing
+        assert!(PixelBuffer::new(u32::MAX, u32::MAX, ColourType::TruecolourAlpha, 16).is_err());
+        assert!(filtered_length(u32::MAX, u32::MAX, ColourType::TruecolourAlpha, 16).is_err());
+
+        // a huge header with little data is rejected before the buffer is allocated
+        let side = (1 << 31) - 1;
+        assert!(PixelBuffer::decode(&[0; 16], side, side, ColourType::TruecolourAlpha, 8).is_err());
+        assert!(crate::interlace::decode(&[0; 16], side, side, ColourType::TruecolourAlpha, 8).is_err());
+    }
+
+    #[test]
+    fn test_set_sample_masks_bit_depth() {
+        let mut pixels = PixelBuffer::new(2, 2, ColourType::Greyscale, 4).unwrap();
+        pixels.set_sample(1, 0xff);
+        assert_eq!(pixels.sample(1), 0x0f);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        assert!(PixelBuffer::new(2, 2, ColourType::Truecolour, 4).is_err());
+        assert!(PixelBuffer::new(2, 2, ColourType::Indexed, 16).is_err());
+        assert!(ColourType::try_from(5).is_err());
+    }
+}
@@ -1,22 +1,35 @@
 use std::{fmt, path::Path, fs};
+use std::io::{Read, Write};
+use std::str::FromStr;
 
-use crate::{chunk::Chunk, Error};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, Error};
+use crate::ihdr::{Ihdr, InterlaceMethod};
+use crate::{disguise, envelope, fragment, interlace};
+use crate::pixels::{self, PixelBuffer};
 
 // PNG
 #[derive(Debug)]
 pub struct Png {
     signature: [u8; 8],
-    chunks: Vec<Chunk>
+    chunks: Vec<Chunk>,
+    trailer: Vec<u8>    // bytes following IEND, ignored by decoders
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];     // default PNG file signature
+    pub const IDAT_CHUNK_SIZE: usize = 8192;    // maximum data size of IDAT chunks written by set_image_data
 
     // Creates PNG from chunks using correct header
+    #[cfg(test)]
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
             signature: Png::STANDARD_HEADER,
-            chunks
+            chunks,
+            trailer: Vec::new()
         }
     }
 
@@ -24,34 +37,81 @@ impl Png {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Png, Error> {
         let png_bytes: &[u8] = &fs::read(path)?;
         
-        Ok(Png::try_from(png_bytes).unwrap())
+        Png::try_from(png_bytes)
     }
 
-    // Appends chunk to end of PNG's chunk list
+    // Appends chunk to end of PNG's chunk list, after IEND if present
+    #[cfg(test)]
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
-    // Removes first instance of chunk with matching type
-    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
-        let mut iterator = self.chunks.iter();
-        let mut index_to_remove =  18446744073709551615;     // assign to very large number hopefully greater than number of chunks
-
-        let mut index: usize = 0;
-        while let Some(chunk) = iterator.next() {
-            if chunk.chunk_type().to_string()==chunk_type {
-                index_to_remove = index;
-            }
-            index+=1;
+    // Inserts chunk at index in PNG's chunk list
+    pub fn insert_at(&mut self, index: usize, chunk: Chunk) -> Result<(), Error> {
+        if index > self.chunks.len() {
+            return Err(format!("Chunk index {} is out of range, PNG has {} chunks", index, self.chunks.len()).into());
         }
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
 
-        if index_to_remove!= 18446744073709551615 {
-            return Ok(self.chunks.remove(index_to_remove.try_into().unwrap()))
-        }
+    // Inserts chunk before the first instance of chunk with matching type
+    pub fn insert_before(&mut self, chunk_type: &str, chunk: Chunk) -> Result<(), Error> {
+        let index = self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string()==chunk_type)
+            .ok_or_else(|| Error::InvalidData(format!("PNG has no {} chunk", chunk_type)))?;
+        self.insert_at(index, chunk)
+    }
 
-        return Err("Chunk with matching type not found".into())
+    // Inserts chunk after the last instance of chunk with matching type, keeping consecutive chunks like IDAT together
+    pub fn insert_after(&mut self, chunk_type: &str, chunk: Chunk) -> Result<(), Error> {
+        let index = self.chunks
+            .iter()
+            .rposition(|chunk| chunk.chunk_type().to_string()==chunk_type)
+            .ok_or_else(|| Error::InvalidData(format!("PNG has no {} chunk", chunk_type)))?;
+        self.insert_at(index + 1, chunk)
+    }
+
+    // Index where a chunk of the given type belongs according to the PNG chunk ordering rules
+    pub fn insert_position(&self, chunk_type: &ChunkType) -> usize {
+        let first_of = |types: &[&str]| self.chunks.iter().position(|chunk| types.contains(&chunk.chunk_type().to_string().as_str()));
+        let end = first_of(&["IEND"]).unwrap_or(self.chunks.len());
+
+        let position = match chunk_type.to_string().as_str() {
+            // must precede PLTE & IDAT
+            "cHRM" | "cICP" | "gAMA" | "iCCP" | "mDCV" | "cLLI" | "sBIT" | "sRGB" => first_of(&["PLTE", "IDAT"]),
+            // must follow PLTE & precede IDAT, or only precede IDAT
+            "bKGD" | "hIST" | "tRNS" | "eXIf" | "pHYs" | "sPLT" => first_of(&["IDAT"]),
+            // any other chunk may go anywhere between IHDR & IEND, so keep it after the image data
+            _ => None
+        };
+
+        position.unwrap_or(end).min(end)
+    }
+
+    // Inserts chunk at the position the PNG chunk ordering rules require, never after IEND
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        let index = self.insert_position(chunk.chunk_type());
+        self.chunks.insert(index, chunk);
+    }
+
+    // Removes first instance of chunk with matching type
+    pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
+        let index = self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string()==chunk_type)
+            .ok_or("Chunk with matching type not found")?;
+        Ok(self.chunks.remove(index))
     }
     
+    // Removes every chunk for which f returns false, returning the number of chunks removed
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, f: F) -> usize {
+        let count = self.chunks.len();
+        self.chunks.retain(f);
+        count - self.chunks.len()
+    }
+
     // Header of PNG
     pub fn header(&self) -> &[u8; 8] {
         &self.signature
@@ -64,57 +124,216 @@ impl Png {
 
     // Find & return first instance of chunk with matching type
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        let mut iterator = self.chunks.iter();
+        self.chunks.iter().find(|chunk| chunk.chunk_type().to_string()==chunk_type)
+    }
 
-        while let Some(chunk) = iterator.next() {
-            if chunk.chunk_type().to_string()==chunk_type {
-                return Some(chunk)
-            }
+    // Bytes following IEND
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    // Replace the bytes following IEND
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    // Byte offset of every chunk in the file, in chunk order
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .scan(self.signature.len(), |offset, chunk| {
+                let start = *offset;
+                *offset += chunk.length() as usize + Chunk::OVERHEAD;
+                Some(start)
+            })
+            .collect()
+    }
+
+    // Return all chunks with matching type in file order
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        self.chunks.iter().filter(|chunk| chunk.chunk_type().to_string()==chunk_type).collect()
+    }
+
+    // Concatenate the data of all IDAT chunks & decompress it, returning the filtered scanlines the dimensions in IHDR require
+    pub fn image_data(&self) -> Result<Vec<u8>, Error> {
+        let compressed: Vec<u8> = self.chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string()=="IDAT")
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+
+        if compressed.is_empty() {
+            return Err(Error::InvalidData("PNG has no IDAT chunks".to_string()));
+        }
+
+        let ihdr = self.ihdr()?;
+        let geometry = (ihdr.width(), ihdr.height(), ihdr.colour_type(), ihdr.bit_depth());
+        let length = match ihdr.interlace_method() {
+            InterlaceMethod::None => pixels::filtered_length(geometry.0, geometry.1, geometry.2, geometry.3)?,
+            InterlaceMethod::Adam7 => interlace::filtered_length(geometry.0, geometry.1, geometry.2, geometry.3)?
+        };
+
+        // stop inflating one byte past what the header allows, so a small IDAT cannot expand without bound
+        let mut data = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(length as u64 + 1)
+            .read_to_end(&mut data)
+            .map_err(|e| Error::InvalidData(format!("Invalid compressed image data: {}", e)))?;
+        if data.len() > length {
+            return Err(Error::InvalidData(format!("Image data expands beyond the {} bytes its dimensions require", length)));
+        }
+
+        Ok(data)
+    }
+
+    // Compress filtered scanlines & replace all IDAT chunks with new ones at the position of the first
+    pub fn set_image_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data)?;
+        let compressed = encoder.finish()?;
+
+        let position = self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string()=="IDAT")
+            .ok_or_else(|| Error::InvalidData("PNG has no IDAT chunks".to_string()))?;
+        self.chunks.retain(|chunk| chunk.chunk_type().to_string()!="IDAT");
+
+        let new_chunks = compressed
+            .chunks(Png::IDAT_CHUNK_SIZE)
+            .map(|data| Ok(Chunk::new(ChunkType::from_str("IDAT")?, data.to_vec())))
+            .collect::<Result<Vec<Chunk>, Error>>()?;
+        self.chunks.splice(position..position, new_chunks);
+
+        Ok(())
+    }
+
+    // Parse the IHDR chunk
+    pub fn ihdr(&self) -> Result<Ihdr, Error> {
+        let chunk = self.chunk_by_type("IHDR").ok_or_else(|| Error::InvalidData("PNG has no IHDR chunk".to_string()))?;
+        Ihdr::try_from(chunk)
+    }
+
+    // Decompress & unfilter the image data into a pixel buffer, following the interlace method in IHDR
+    pub fn pixels(&self) -> Result<PixelBuffer, Error> {
+        let ihdr = self.ihdr()?;
+        let data = self.image_data()?;
+        match ihdr.interlace_method() {
+            InterlaceMethod::None => PixelBuffer::decode(&data, ihdr.width(), ihdr.height(), ihdr.colour_type(), ihdr.bit_depth()),
+            InterlaceMethod::Adam7 => interlace::decode(&data, ihdr.width(), ihdr.height(), ihdr.colour_type(), ihdr.bit_depth())
         }
+    }
 
-        return None
+    // Filter & compress a pixel buffer into new IDAT chunks, pixels must match the geometry in IHDR & are interlaced like the image was
+    pub fn set_pixels(&mut self, pixels: &PixelBuffer) -> Result<(), Error> {
+        let ihdr = self.ihdr()?;
+        let geometry = (pixels.width(), pixels.height(), pixels.colour_type(), pixels.bit_depth());
+        if (ihdr.width(), ihdr.height(), ihdr.colour_type(), ihdr.bit_depth()) != geometry {
+            return Err("Pixel buffer does not match the image header".into());
+        }
+        match ihdr.interlace_method() {
+            InterlaceMethod::None => self.set_image_data(&pixels.encode()),
+            InterlaceMethod::Adam7 => self.set_image_data(&interlace::encode(pixels)?)
+        }
+    }
+
+    // Re-encode the image data with another interlace method, updating IHDR
+    pub fn set_interlace_method(&mut self, interlace_method: InterlaceMethod) -> Result<(), Error> {
+        let ihdr = self.ihdr()?;
+        if ihdr.interlace_method() == interlace_method {
+            return Ok(());
+        }
 
+        let pixels = self.pixels()?;
+        let new_ihdr = Ihdr::new(ihdr.width(), ihdr.height(), ihdr.bit_depth(), ihdr.colour_type(), interlace_method)?;
+        let position = self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string()=="IHDR")
+            .ok_or_else(|| Error::InvalidData("PNG has no IHDR chunk".to_string()))?;
+        self.chunks[position] = new_ihdr.to_chunk();
+        self.set_pixels(&pixels)
     }
 
-    // Convert PNG to byte array containing header followed by chunks
+    // Convert PNG to byte array containing header followed by chunks & trailer
     pub fn as_bytes(&self) -> Vec<u8> {
-        [self.signature.to_vec(), self.chunks.iter().flat_map(|chunk| chunk.as_bytes()).collect()].concat()
+        [self.signature.to_vec(), self.chunks.iter().flat_map(|chunk| chunk.as_bytes()).collect(), self.trailer.clone()].concat()
     }
 
 
 }
 
-impl TryFrom<&[u8]> for Png {
-    type Error = &'static str;
+// Check if chunk data is something we wrote, i.e. an envelope, possibly error corrected, a fragment or a text entry disguising one
+fn holds_our_data(chunk_type: &[u8], data: &[u8]) -> bool {
+    envelope::holds_envelope(data)
+        || fragment::is_fragment(data)
+        || std::str::from_utf8(chunk_type).is_ok_and(|chunk_type| disguise::is_carrier(chunk_type, data))
+}
 
-    fn try_from(bytes: &[u8]) -> std::result::Result<Self, Self::Error> {
-        let signature: [u8; 8] = bytes[0..8].try_into().unwrap();
+impl Png {
+    // Parse PNG bytes, ignoring CRC mismatches in ancillary chunks holding our envelopes, fragments or disguised text entries, returning the indices of chunks whose CRC was wrong
+    pub fn parse_tolerant(bytes: &[u8]) -> Result<(Png, Vec<usize>), Error> {
+        Png::parse(bytes, true)
+    }
+
+    fn parse(bytes: &[u8], tolerate_crc: bool) -> Result<(Png, Vec<usize>), Error> {
+        let signature: [u8; 8] = bytes
+            .get(0..8)
+            .and_then(|signature| signature.try_into().ok())
+            .ok_or(Error::BadSignature)?;
 
         // make sure PNG signature is valid
-        if signature!=Png::STANDARD_HEADER {
-            return Err("Invalid signature")
+        if signature != Png::STANDARD_HEADER {
+            return Err(Error::BadSignature);
         }
 
         let mut chunks: Vec<Chunk> = Vec::new();
+        let mut corrupted = Vec::new();
 
         // process individual chunk from bytes
         let mut chunk_index = 8;
 
-        while chunk_index < bytes.len() {
-            let chunk_length: usize = u32::from_be_bytes(bytes[chunk_index..chunk_index+4].try_into().unwrap()).try_into().unwrap();
-        
-            let chunk_bytes: &[u8] = bytes[chunk_index..chunk_index + chunk_length + 12].try_into().unwrap();
-            let chunk = Chunk::try_from(chunk_bytes).unwrap();
+        // anything after IEND is kept as is rather than parsed as chunks, like decoders do
+        while chunk_index < bytes.len() && chunks.last().is_none_or(|chunk| chunk.chunk_type().to_string() != "IEND") {
+            let offset = chunk_index;
+            let rest = &bytes[offset..];
+            if rest.len() < Chunk::OVERHEAD {
+                return Err(Error::TruncatedChunk { offset });
+            }
+
+            let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]);
+            if length > Chunk::MAX_LENGTH {
+                return Err(Error::LengthOverflow { offset, length });
+            }
+
+            let chunk_length = length as usize + Chunk::OVERHEAD;
+            let chunk_bytes = rest.get(..chunk_length).ok_or(Error::TruncatedChunk { offset })?;
+            let chunk = match Chunk::try_from(chunk_bytes) {
+                // damaged image chunks are never accepted, the image itself would be wrong, nor are other programs' chunks
+                Err(Error::CrcMismatch { .. }) if tolerate_crc && !chunk_bytes[4].is_ascii_uppercase() && holds_our_data(&chunk_bytes[4..8], &chunk_bytes[8..chunk_length - 4]) => {
+                    corrupted.push(chunks.len());
+                    let chunk_type = ChunkType::try_from([chunk_bytes[4], chunk_bytes[5], chunk_bytes[6], chunk_bytes[7]])?;
+                    Chunk::new(chunk_type, chunk_bytes[8..chunk_length - 4].to_vec())
+                },
+                result => result?
+            };
             chunks.push(chunk);
 
-            chunk_index = chunk_index + chunk_length + 12;
+            chunk_index += chunk_length;
         }
 
-        Ok(Png { 
+        let png = Png { 
             signature, 
-            chunks
-        })
+            chunks,
+            trailer: bytes[chunk_index..].to_vec()
+        };
+        Ok((png, corrupted))
+    }
+}
+
+impl TryFrom<&[u8]> for Png {
+    type Error = Error;
 
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Png::parse(bytes, false).map(|(png, _)| png)
     }
 }
 
@@ -122,6 +341,13 @@ impl fmt::Display for Png {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Signature: {:?}", self.header())?;
         writeln!(f, "Number of chunks: {}", self.chunks().len())?;
+        match self.ihdr() {
+            Ok(ihdr) => write!(f, "{}", ihdr)?,
+            Err(e) => writeln!(f, "IHDR: {}", e)?
+        }
+        if !self.trailer.is_empty() {
+            writeln!(f, "Trailing data: {} bytes after IEND", self.trailer.len())?;
+        }
         Ok(())
     }
 }
@@ -134,16 +360,20 @@ mod tests {
     use crate::Error;
     use crate::chunk_type::ChunkType;
     use crate::chunk::Chunk;
+    use crate::compression::Algorithm;
+    use crate::disguise::TextEntry;
+    use crate::envelope::Envelope;
+    use crate::pixels::ColourType;
+    use crate::test_support::placeholder_png;
+    use crate::text::TextChunk;
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap()
+        ]
     }
 
     fn testing_png() -> Png {
@@ -227,6 +457,110 @@ mod tests {
     }
 
 
+    fn testing_png_bytes() -> Vec<u8> {
+        [&Png::STANDARD_HEADER[..], &testing_png().as_bytes()[8..]].concat()
+    }
+
+    #[test]
+    fn test_bad_signature_error() {
+        assert!(matches!(Png::try_from(&b"\x89PN"[..]), Err(Error::BadSignature)));
+        assert!(matches!(Png::try_from(&[0; 32][..]), Err(Error::BadSignature)));
+    }
+
+    #[test]
+    fn test_truncated_chunk_error() {
+        let bytes = testing_png_bytes();
+        // second chunk starts after the signature & first chunk holding 20 data bytes
+        let offset = 8 + Chunk::OVERHEAD + 20;
+        assert!(matches!(Png::try_from(&bytes[..offset + 3]), Err(Error::TruncatedChunk { offset: o }) if o == offset));
+        assert!(matches!(Png::try_from(&bytes[..offset + 15]), Err(Error::TruncatedChunk { offset: o }) if o == offset));
+    }
+
+    #[test]
+    fn test_corrupt_chunk_errors() {
+        let mut bytes = testing_png_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(matches!(Png::try_from(bytes.as_ref()), Err(Error::CrcMismatch { .. })));
+
+        let mut bytes = testing_png_bytes();
+        bytes[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(Png::try_from(bytes.as_ref()), Err(Error::LengthOverflow { offset: 8, length: u32::MAX })));
+    }
+
+    #[test]
+    fn test_trailer_after_iend() {
+        let iend = Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new());
+        let bytes = [testing_png_bytes(), iend.as_bytes(), b"Not a chunk".to_vec()].concat();
+
+        let png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.trailer(), b"Not a chunk");
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(png.to_string().contains("Trailing data: 11 bytes after IEND"));
+    }
+
+    #[test]
+    fn test_parse_tolerant() {
+        let chunks = || vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"IENVSecret".to_vec()),
+            Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0Secret".to_vec()),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new())
+        ];
+        let mut bytes = Png::from_chunks(chunks()).as_bytes();
+
+        // flip a data byte of our chunk, keeping its stored CRC
+        let data_offset = 8 + (Chunk::OVERHEAD + 13) + 8;
+        bytes[data_offset + 4] ^= 0x20;
+        assert!(matches!(Png::try_from(bytes.as_ref()), Err(Error::CrcMismatch { .. })));
+
+        let (png, corrupted) = Png::parse_tolerant(&bytes).unwrap();
+        assert_eq!(corrupted, vec![1]);
+        assert_eq!(png.chunks()[1].data(), b"IENVsecret");
+
+        // ancillary chunks we did not write must still be intact
+        let mut bytes = Png::from_chunks(chunks()).as_bytes();
+        bytes[data_offset + (Chunk::OVERHEAD + 10) + 8] ^= 0x20;
+        assert!(matches!(Png::parse_tolerant(&bytes), Err(Error::CrcMismatch { .. })));
+
+        // critical chunks must still be intact
+        let mut bytes = Png::from_chunks(chunks()).as_bytes();
+        bytes[8 + 8] ^= 1;
+        assert!(matches!(Png::parse_tolerant(&bytes), Err(Error::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_parse_tolerant_text_carrier() {
+        let envelope = Envelope::seal(0, Algorithm::None, b"Secret", b"Secret ".repeat(40)).as_bytes();
+        for entry in [TextEntry::Comment, TextEntry::Xmp] {
+            let mut png = placeholder_png(&["IHDR", "IDAT", "IEND"]);
+            disguise::hide(&mut png, &envelope, entry).unwrap();
+
+            // damage the end of the text, away from the envelope header it is recognised by
+            let mut bytes = png.as_bytes();
+            let end = png.chunk_offsets()[2] - 5;
+            bytes[end] ^= 0x20;
+            let (_, corrupted) = Png::parse_tolerant(&bytes).unwrap();
+            assert_eq!(corrupted, vec![1]);
+        }
+
+        // ordinary comments are not ours to repair
+        let mut png = placeholder_png(&["IHDR", "IDAT", "IEND"]);
+        let comment = TextChunk::Text { keyword: "Comment".to_string(), text: "Created with GIMP".to_string() };
+        png.insert_before("IDAT", comment.to_chunk().unwrap()).unwrap();
+        let mut bytes = png.as_bytes();
+        bytes[png.chunk_offsets()[2] - 5] ^= 0x20;
+        assert!(matches!(Png::parse_tolerant(&bytes), Err(Error::CrcMismatch { .. })));
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = testing_png();
+        // chunks hold 20, 18 & 19 data bytes
+        assert_eq!(png.chunk_offsets(), vec![8, 8 + 12 + 20, 8 + 12 + 20 + 12 + 18]);
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -252,6 +586,58 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+    }
+
+    #[test]
+    fn test_insert_at() {
+        let mut png = testing_png();
+        png.insert_at(1, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["FrSt", "TeSt", "miDl", "LASt"]);
+        png.insert_at(4, chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(chunk_types(&png).last().unwrap(), "TeSt");
+        assert!(png.insert_at(6, chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_insert_before_after() {
+        let mut png = testing_png();
+        png.insert_after("FrSt", chunk_from_strings("miDl", "Another").unwrap()).unwrap();
+        png.insert_after("miDl", chunk_from_strings("AfTr", "After").unwrap()).unwrap();
+        png.insert_before("miDl", chunk_from_strings("BeFr", "Before").unwrap()).unwrap();
+        assert_eq!(chunk_types(&png), vec!["FrSt", "BeFr", "miDl", "miDl", "AfTr", "LASt"]);
+        assert!(png.insert_before("NoNe", chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+        assert!(png.insert_after("NoNe", chunk_from_strings("TeSt", "Message").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_ordering() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "Message").unwrap());
+        png.insert_chunk(chunk_from_strings("gAMA", "Gamma").unwrap());
+        png.insert_chunk(chunk_from_strings("pHYs", "Physical").unwrap());
+        png.insert_chunk(chunk_from_strings("cICP", "Code").unwrap());
+        png.insert_chunk(chunk_from_strings("eXIf", "Exif").unwrap());
+
+        let types = chunk_types(&png);
+        let index = |chunk_type: &str| types.iter().position(|t| t == chunk_type).unwrap();
+        assert_eq!(types.last().unwrap(), "IEND");
+        assert_eq!(index("ruSt"), types.len() - 2);
+        assert!(index("gAMA") < index("IDAT"));
+        assert!(index("pHYs") < index("IDAT"));
+        assert!(index("cICP") < index("IDAT"));
+        assert!(index("eXIf") < index("IDAT"));
+        assert!(index("IHDR") < index("gAMA"));
+    }
+
+    #[test]
+    fn test_insert_chunk_without_iend() {
+        let mut png = testing_png();
+        png.insert_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        assert_eq!(chunk_types(&png).last().unwrap(), "TeSt");
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -261,6 +647,23 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_remove_first_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("TeSt", "First").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Second").unwrap());
+        assert_eq!(png.remove_chunk("TeSt").unwrap().data(), b"First");
+        assert_eq!(png.chunk_by_type("TeSt").unwrap().data(), b"Second");
+        assert!(png.remove_chunk("NoNe").is_err());
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        assert_eq!(png.retain_chunks(|chunk| chunk.chunk_type().is_critical()), 1);
+        assert_eq!(chunk_types(&png), vec!["FrSt", "LASt"]);
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -270,9 +673,124 @@ mod tests {
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
-        let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
-        assert_eq!(actual, expected);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_image_data_roundtrip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let data = png.image_data().unwrap();
+        assert_eq!(data.len(), (50 * 4 + 1) * 50);
+
+        png.set_image_data(&data).unwrap();
+        assert_eq!(png.image_data().unwrap(), data);
+        assert_eq!(png.chunks()[0].chunk_type().to_string(), "IHDR");
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_image_data_limit() {
+        // a few kilobytes of IDAT inflating far past what the 50x50 header allows are rejected
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.set_image_data(&vec![0; 10_000_000]).unwrap();
+        assert!(matches!(png.image_data(), Err(Error::InvalidData(_))));
+    }
+
+    #[test]
+    fn test_set_image_data_splits_chunks() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        // xorshift noise the size of the image so the data does not compress into a single chunk
+        let mut state = 2463534242u32;
+        let data: Vec<u8> = (0..(50 * 4 + 1) * 50)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        png.set_image_data(&data).unwrap();
+
+        let idat_chunks: Vec<&Chunk> = png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string()=="IDAT").collect();
+        assert!(idat_chunks.len() > 1);
+        assert!(idat_chunks.iter().all(|chunk| chunk.data().len() <= Png::IDAT_CHUNK_SIZE));
+        assert_eq!(png.image_data().unwrap(), data);
+    }
+
+    #[test]
+    fn test_ihdr_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width(), ihdr.height()), (50, 50));
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.colour_type(), ColourType::TruecolourAlpha);
+        assert_eq!(ihdr.interlace_method(), InterlaceMethod::None);
+        assert!(png.to_string().contains("Width: 50"));
+    }
+
+    #[test]
+    fn test_ihdr_missing() {
+        let png = testing_png();
+        assert!(png.ihdr().is_err());
+        assert!(png.to_string().contains("IHDR: PNG has no IHDR chunk"));
+    }
+
+    #[test]
+    fn test_pixels_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = png.pixels().unwrap();
+        assert_eq!((pixels.width(), pixels.height()), (50, 50));
+        assert_eq!(pixels.colour_type(), ColourType::TruecolourAlpha);
+        assert_eq!(pixels.len(), 50 * 50 * 4);
+    }
+
+    #[test]
+    fn test_set_pixels_roundtrip() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut pixels = png.pixels().unwrap();
+        let index = pixels.index(10, 20, 2);
+        pixels.set_sample(index, 123);
+
+        png.set_pixels(&pixels).unwrap();
+        let bytes = png.as_bytes();
+        let decoded = Png::try_from(bytes.as_ref()).unwrap().pixels().unwrap();
+        assert_eq!(decoded, pixels);
+        assert_eq!(decoded.pixel(10, 20)[2], 123);
+    }
+
+    #[test]
+    fn test_interlace_conversion() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = png.pixels().unwrap();
+
+        png.set_interlace_method(InterlaceMethod::Adam7).unwrap();
+        let interlaced = Png::try_from(png.as_bytes().as_ref()).unwrap();
+        assert_eq!(interlaced.ihdr().unwrap().interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(interlaced.chunks()[0].chunk_type().to_string(), "IHDR");
+        assert_eq!(interlaced.pixels().unwrap(), pixels);
+
+        // changed pixels keep the interlacing
+        let mut changed = pixels.clone();
+        changed.set_sample(changed.index(3, 5, 0), 7);
+        png.set_pixels(&changed).unwrap();
+        assert_eq!(png.ihdr().unwrap().interlace_method(), InterlaceMethod::Adam7);
+        assert_eq!(png.pixels().unwrap(), changed);
+
+        png.set_interlace_method(InterlaceMethod::None).unwrap();
+        assert_eq!(png.ihdr().unwrap().interlace_method(), InterlaceMethod::None);
+        assert_eq!(png.pixels().unwrap(), changed);
+    }
+
+    #[test]
+    fn test_set_pixels_wrong_geometry() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = PixelBuffer::new(10, 10, ColourType::TruecolourAlpha, 8).unwrap();
+        assert!(png.set_pixels(&pixels).is_err());
+    }
+
+    #[test]
+    fn test_image_data_missing() {
+        assert!(testing_png().image_data().is_err());
     }
 
     #[test]
@@ -0,0 +1,238 @@
+use std::str::FromStr;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::keys::{Signer, SigningIdentity, SIGNATURE_LENGTH};
+use crate::png::Png;
+
+pub const SIGNATURE_CHUNK_TYPE: &str = "siGn";     // ancillary, private, safe to copy
+const MAGIC: [u8; 4] = *b"ISIG";
+const FORMAT_VERSION: u8 = 1;
+const FLAG_CRITICAL_CHUNKS: u8 = 1;                 // signature also covers all critical chunks
+const CONTEXT: &[u8] = b"imgcrypt payload signature v1";
+const SIGNATURE_DATA_LENGTH: usize = MAGIC.len() + 2 + 32 + 4 + SIGNATURE_LENGTH;
+
+// Outcome of checking a single signature chunk
+#[derive(Debug)]
+pub struct SignatureReport {
+    pub signer: Signer,         // public key that made the signature
+    pub payload_type: String,   // chunk type of the signed payload
+    pub valid: bool,            // whether the signature matches the covered chunks
+    pub covered: Vec<String>    // types of all chunks covered by the signature, in file order
+}
+
+// Contents of a signature chunk's data field
+struct SignatureData {
+    flags: u8,
+    signer: Signer,
+    payload_type: [u8; 4],
+    signature: [u8; SIGNATURE_LENGTH]
+}
+
+impl SignatureData {
+    // Convert to bytes containing in order: magic, version, flags, signer public key, payload chunk type & signature
+    fn as_bytes(&self) -> Vec<u8> {
+        MAGIC
+            .iter()
+            .chain([FORMAT_VERSION, self.flags].iter())
+            .chain(self.signer.bytes().iter())
+            .chain(self.payload_type.iter())
+            .chain(self.signature.iter())
+            .copied()
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<SignatureData> {
+        if bytes.len() != SIGNATURE_DATA_LENGTH || !bytes.starts_with(&MAGIC) {
+            return Err(Error::InvalidData("Malformed signature chunk".to_string()));
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(Error::InvalidData(format!("Unsupported signature format version {}", bytes[4])));
+        }
+
+        let signer_bytes: [u8; 32] = bytes[6..38].try_into().unwrap();
+        Ok(SignatureData {
+            flags: bytes[5],
+            signer: Signer::try_from(signer_bytes)?,
+            payload_type: bytes[38..42].try_into().unwrap(),
+            signature: bytes[42..].try_into().unwrap()
+        })
+    }
+}
+
+// Build the message that is signed, returning it with the types of the chunks it covers
+fn signed_message(png: &Png, payload_type: &str, flags: u8) -> Result<(Vec<u8>, Vec<String>)> {
+    // the signature names the payload by type only, so a second chunk of that type would leave it unclear which one is covered
+    let payload = match png.chunks_by_type(payload_type)[..] {
+        [payload] => payload,
+        [] => return Err(Error::Usage(format!("No chunk of type {} to sign", payload_type))),
+        _ => return Err(Error::Usage(format!("Image has more than one chunk of type {}, the signed one would be ambiguous", payload_type)))
+    };
+
+    let mut message = [CONTEXT, &[flags]].concat();
+    let mut covered = vec![payload_type.to_string()];
+    message.extend(payload.as_bytes());
+
+    if flags & FLAG_CRITICAL_CHUNKS != 0 {
+        for chunk in png.chunks().iter().filter(|chunk| chunk.chunk_type().is_critical()) {
+            message.extend(chunk.as_bytes());
+            covered.push(chunk.chunk_type().to_string());
+        }
+    }
+
+    Ok((message, covered))
+}
+
+// Sign the only chunk of the given type, and optionally all critical chunks, returning a signature chunk
+pub fn sign(png: &Png, payload_type: &str, key: &SigningIdentity, include_critical: bool) -> Result<Chunk> {
+    let flags = if include_critical { FLAG_CRITICAL_CHUNKS } else { 0 };
+    let (message, _) = signed_message(png, payload_type, flags)?;
+
+    let data = SignatureData {
+        flags,
+        signer: key.signer(),
+        payload_type: ChunkType::from_str(payload_type)?.bytes(),
+        signature: key.sign(&message)
+    };
+
+    Ok(Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK_TYPE)?, data.as_bytes()))
+}
+
+// Check every signature chunk in the PNG
+pub fn verify(png: &Png) -> Result<Vec<SignatureReport>> {
+    let mut reports = Vec::new();
+
+    for chunk in png.chunks().iter().filter(|chunk| chunk.chunk_type().to_string() == SIGNATURE_CHUNK_TYPE) {
+        let data = SignatureData::from_bytes(chunk.data())?;
+        let payload_type = String::from_utf8_lossy(&data.payload_type).to_string();
+
+        let report = match signed_message(png, &payload_type, data.flags) {
+            Ok((message, covered)) => SignatureReport {
+                signer: data.signer,
+                payload_type,
+                valid: data.signer.verify(&message, &data.signature),
+                covered
+            },
+            // signed payload chunk has been removed or another of its type added
+            Err(_) => SignatureReport {
+                signer: data.signer,
+                covered: vec![payload_type.clone()],
+                payload_type,
+                valid: false
+            }
+        };
+        reports.push(report);
+    }
+
+    Ok(reports)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        let chunks = vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![1, 2, 3, 4]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Secret message".to_vec()),
+        ];
+        Png::from_chunks(chunks)
+    }
+
+    fn signed_png(include_critical: bool) -> (Png, SigningIdentity) {
+        let mut png = testing_png();
+        let key = SigningIdentity::generate();
+        let signature = sign(&png, "ruSt", &key, include_critical).unwrap();
+        png.append_chunk(signature);
+        (png, key)
+    }
+
+    // Rebuild PNG with the data of the first chunk of the given type replaced
+    fn replace_chunk(png: &Png, chunk_type: &str, data: Vec<u8>) -> Png {
+        let mut data = Some(data);
+        let chunks = png
+            .chunks()
+            .iter()
+            .map(|chunk| {
+                let replace = chunk.chunk_type().to_string() == chunk_type;
+                let chunk_data = if replace { data.take() } else { None };
+                Chunk::new(ChunkType::try_from(chunk.chunk_type().bytes()).unwrap(), chunk_data.unwrap_or_else(|| chunk.data().to_vec()))
+            })
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_valid_signature() {
+        let (png, key) = signed_png(false);
+        let reports = verify(&png).unwrap();
+
+        assert_eq!(reports.len(), 1);
+        assert!(reports[0].valid);
+        assert_eq!(reports[0].signer, key.signer());
+        assert_eq!(reports[0].covered, vec!["ruSt"]);
+    }
+
+    #[test]
+    fn test_signature_covers_critical_chunks() {
+        let (png, _) = signed_png(true);
+        let reports = verify(&png).unwrap();
+
+        assert!(reports[0].valid);
+        assert_eq!(reports[0].covered, vec!["ruSt", "IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_tampered_payload() {
+        let (png, _) = signed_png(false);
+        let png = replace_chunk(&png, "ruSt", b"Altered message".to_vec());
+        assert!(!verify(&png).unwrap()[0].valid);
+    }
+
+    #[test]
+    fn test_tampered_critical_chunk() {
+        let (png, _) = signed_png(true);
+        let png = replace_chunk(&png, "IDAT", vec![4, 3, 2, 1]);
+        assert!(!verify(&png).unwrap()[0].valid);
+
+        // critical chunks are not covered unless requested
+        let (png, _) = signed_png(false);
+        let png = replace_chunk(&png, "IDAT", vec![4, 3, 2, 1]);
+        assert!(verify(&png).unwrap()[0].valid);
+    }
+
+    #[test]
+    fn test_removed_payload() {
+        let (mut png, _) = signed_png(false);
+        png.remove_chunk("ruSt").unwrap();
+        assert!(!verify(&png).unwrap()[0].valid);
+    }
+
+    #[test]
+    fn test_duplicate_payload_type() {
+        // an earlier chunk of the same type must not be signed in place of the new one
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"New message".to_vec()));
+        assert!(sign(&png, "ruSt", &SigningIdentity::generate(), false).is_err());
+
+        // nor can a chunk of the signed type be added next to the signed one
+        let (mut png, _) = signed_png(false);
+        png.append_chunk(Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Unsigned message".to_vec()));
+        assert!(!verify(&png).unwrap()[0].valid);
+    }
+
+    #[test]
+    fn test_sign_missing_chunk() {
+        let png = testing_png();
+        assert!(sign(&png, "miSs", &SigningIdentity::generate(), false).is_err());
+    }
+
+    #[test]
+    fn test_no_signatures() {
+        assert!(verify(&testing_png()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,15 @@
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+// Chunk of the given type holding data
+pub fn chunk_of(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+}
+
+// PNG made of chunks of the given types, each holding 4 placeholder bytes
+pub fn placeholder_png(chunk_types: &[&str]) -> Png {
+    Png::from_chunks(chunk_types.iter().map(|chunk_type| chunk_of(chunk_type, &[0; 4])).collect())
+}
@@ -0,0 +1,289 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Error, Result};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::compression::{self, Algorithm};
+
+pub const TEXT_CHUNK_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+const MAX_KEYWORD_LENGTH: usize = 79;
+const COMPRESSION_METHOD: u8 = 0;   // zlib deflate, the only method defined
+
+// Textual metadata stored in a tEXt, zTXt or iTXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextChunk {
+    // tEXt, uncompressed Latin-1 text
+    Text { keyword: String, text: String },
+
+    // zTXt, compressed Latin-1 text
+    CompressedText { keyword: String, text: String },
+
+    // iTXt, optionally compressed UTF-8 text with a language tag & keyword translated into that language
+    InternationalText { keyword: String, compressed: bool, language_tag: String, translated_keyword: String, text: String }
+}
+
+impl TextChunk {
+    // Keyword identifying the text, e.g. Author or Description
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::CompressedText { keyword, .. }
+            | TextChunk::InternationalText { keyword, .. } => keyword
+        }
+    }
+
+    // Text stored under the keyword
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::CompressedText { text, .. }
+            | TextChunk::InternationalText { text, .. } => text
+        }
+    }
+
+    // Type of chunk holding this text
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            TextChunk::Text { .. } => "tEXt",
+            TextChunk::CompressedText { .. } => "zTXt",
+            TextChunk::InternationalText { .. } => "iTXt"
+        }
+    }
+
+    // Convert to chunk, validating keyword & text
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = encode_keyword(self.keyword())?;
+        data.push(0);
+
+        match self {
+            TextChunk::Text { text, .. } => data.extend(encode_latin1(text)?),
+            TextChunk::CompressedText { text, .. } => {
+                data.push(COMPRESSION_METHOD);
+                data.extend(compression::compress(&encode_latin1(text)?, Algorithm::Deflate)?);
+            },
+            TextChunk::InternationalText { compressed, language_tag, translated_keyword, text, .. } => {
+                if !is_valid_language_tag(language_tag) {
+                    return Err(Error::InvalidData(format!("Invalid language tag \"{}\"", language_tag)));
+                }
+                data.extend([*compressed as u8, COMPRESSION_METHOD]);
+                data.extend(language_tag.as_bytes());
+                data.push(0);
+                data.extend(translated_keyword.as_bytes());
+                data.push(0);
+                if *compressed {
+                    data.extend(compression::compress(text.as_bytes(), Algorithm::Deflate)?);
+                } else {
+                    data.extend(text.as_bytes());
+                }
+            }
+        }
+
+        Ok(Chunk::new(ChunkType::from_str(self.chunk_type())?, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self> {
+        let chunk_type = chunk.chunk_type().to_string();
+        let invalid = |message: &str| Error::InvalidData(format!("Invalid {} chunk: {}", chunk_type, message));
+
+        let (keyword, rest) = split_at_null(chunk.data()).ok_or_else(|| invalid("missing keyword separator"))?;
+        let keyword = decode_latin1(keyword);
+        if !is_valid_keyword(&keyword) {
+            return Err(invalid("invalid keyword"));
+        }
+
+        match chunk_type.as_str() {
+            "tEXt" => Ok(TextChunk::Text { keyword, text: decode_latin1(rest) }),
+            "zTXt" => {
+                let (&method, compressed) = rest.split_first().ok_or_else(|| invalid("missing compression method"))?;
+                if method != COMPRESSION_METHOD {
+                    return Err(invalid("unknown compression method"));
+                }
+                let text = compression::decompress(compressed, Algorithm::Deflate, compression::MAX_TEXT_LENGTH)?;
+                Ok(TextChunk::CompressedText { keyword, text: decode_latin1(&text) })
+            },
+            "iTXt" => {
+                if rest.len() < 2 {
+                    return Err(invalid("missing compression fields"));
+                }
+                let compressed = match rest[0] {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(invalid("invalid compression flag"))
+                };
+                if compressed && rest[1] != COMPRESSION_METHOD {
+                    return Err(invalid("unknown compression method"));
+                }
+
+                let (language_tag, rest) = split_at_null(&rest[2..]).ok_or_else(|| invalid("missing language tag separator"))?;
+                let (translated_keyword, text) = split_at_null(rest).ok_or_else(|| invalid("missing translated keyword separator"))?;
+                let text = if compressed { compression::decompress(text, Algorithm::Deflate, compression::MAX_TEXT_LENGTH)? } else { text.to_vec() };
+
+                Ok(TextChunk::InternationalText {
+                    keyword,
+                    compressed,
+                    language_tag: String::from_utf8(language_tag.to_vec())?,
+                    translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+                    text: String::from_utf8(text)?
+                })
+            },
+            _ => Err(Error::InvalidData(format!("Expected a text chunk, found {}", chunk_type)))
+        }
+    }
+}
+
+impl fmt::Display for TextChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.keyword())?;
+        if let TextChunk::InternationalText { language_tag, translated_keyword, .. } = self {
+            if !language_tag.is_empty() || !translated_keyword.is_empty() {
+                write!(f, " [{}, {}]", language_tag, translated_keyword)?;
+            }
+        }
+        write!(f, " ({}): {}", self.chunk_type(), self.text())
+    }
+}
+
+// Check if chunk type holds textual metadata
+pub fn is_text_chunk(chunk: &Chunk) -> bool {
+    TEXT_CHUNK_TYPES.contains(&chunk.chunk_type().to_string().as_str())
+}
+
+// Keywords are 1 to 79 printable Latin-1 characters without leading, trailing or consecutive spaces
+pub fn is_valid_keyword(keyword: &str) -> bool {
+    let length = keyword.chars().count();
+    (1..=MAX_KEYWORD_LENGTH).contains(&length)
+        && keyword.chars().all(|c| matches!(c as u32, 32..=126 | 161..=255))
+        && !keyword.starts_with(' ')
+        && !keyword.ends_with(' ')
+        && !keyword.contains("  ")
+}
+
+// Language tags are hyphen-separated words of ASCII letters & digits, or empty when unknown
+fn is_valid_language_tag(language_tag: &str) -> bool {
+    language_tag.is_empty()
+        || language_tag.split('-').all(|word| !word.is_empty() && word.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+// Check if text only uses characters of Latin-1 & can be stored in tEXt or zTXt chunks
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) < 256 && c != '\0')
+}
+
+fn encode_keyword(keyword: &str) -> Result<Vec<u8>> {
+    if !is_valid_keyword(keyword) {
+        return Err(Error::Usage(format!("Invalid keyword \"{}\", keywords must be 1 to 79 printable Latin-1 characters without leading, trailing or consecutive spaces", keyword)));
+    }
+    encode_latin1(keyword)
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    if !is_latin1(text) {
+        return Err(Error::Usage("Text must only contain Latin-1 characters, use an iTXt chunk for other text".to_string()));
+    }
+    Ok(text.chars().map(|c| c as u8).collect())
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+// Split bytes at the first null separator, dropping the separator
+fn split_at_null(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|&byte| byte == 0)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(text_chunk: TextChunk) {
+        let chunk = text_chunk.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), text_chunk.chunk_type());
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text_chunk);
+    }
+
+    #[test]
+    fn test_text_chunk_roundtrips() {
+        roundtrip(TextChunk::Text { keyword: "Author".to_string(), text: "Zoë".to_string() });
+        roundtrip(TextChunk::CompressedText { keyword: "Description".to_string(), text: "A long description ".repeat(20) });
+        roundtrip(TextChunk::InternationalText {
+            keyword: "Title".to_string(),
+            compressed: true,
+            language_tag: "ja-JP".to_string(),
+            translated_keyword: "タイトル".to_string(),
+            text: "こんにちは".to_string()
+        });
+        roundtrip(TextChunk::InternationalText {
+            keyword: "Comment".to_string(),
+            compressed: false,
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: "Plain UTF-8 ✓".to_string()
+        });
+    }
+
+    #[test]
+    fn test_text_is_latin1_on_disk() {
+        let chunk = TextChunk::Text { keyword: "Author".to_string(), text: "Zoë".to_string() }.to_chunk().unwrap();
+        assert_eq!(chunk.data(), b"Author\0Zo\xeb");
+    }
+
+    #[test]
+    fn test_keyword_validation() {
+        assert!(is_valid_keyword("Creation Time"));
+        assert!(!is_valid_keyword(""));
+        assert!(!is_valid_keyword(" Author"));
+        assert!(!is_valid_keyword("Author "));
+        assert!(!is_valid_keyword("Creation  Time"));
+        assert!(!is_valid_keyword(&"a".repeat(80)));
+        assert!(!is_valid_keyword("Tab\tKey"));
+        assert!(!is_valid_keyword("タイトル"));
+
+        let text_chunk = TextChunk::Text { keyword: "Bad  Key".to_string(), text: "Text".to_string() };
+        assert!(text_chunk.to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_non_latin1_text_rejected() {
+        let text_chunk = TextChunk::Text { keyword: "Title".to_string(), text: "こんにちは".to_string() };
+        assert!(text_chunk.to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_invalid_text_chunks() {
+        let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec());
+        assert!(TextChunk::try_from(&chunk("tEXt", b"No separator")).is_err());
+        assert!(TextChunk::try_from(&chunk("zTXt", b"Key\0\x00not zlib")).is_err());
+        assert!(TextChunk::try_from(&chunk("iTXt", b"Key\0\x02\x00\0\0Text")).is_err());
+        assert!(TextChunk::try_from(&chunk("iTXt", b"Key\0\x00\x00en")).is_err());
+        assert!(TextChunk::try_from(&chunk("ruSt", b"Key\0Text")).is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_limit() {
+        // text inflating past the limit is rejected instead of filling memory
+        let compressed = compression::compress(&vec![b'a'; compression::MAX_TEXT_LENGTH + 1], Algorithm::Deflate).unwrap();
+        let data = [&b"Comment\0\0"[..], &compressed].concat();
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), data);
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_text_chunk_display() {
+        let text_chunk = TextChunk::InternationalText {
+            keyword: "Title".to_string(),
+            compressed: false,
+            language_tag: "fr".to_string(),
+            translated_keyword: "Titre".to_string(),
+            text: "Bonjour".to_string()
+        };
+        assert_eq!(text_chunk.to_string(), "Title [fr, Titre] (iTXt): Bonjour");
+    }
+}
@@ -0,0 +1,192 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crc::crc32::checksum_ieee;
+
+use crate::{Error, Result};
+use crate::envelope;
+use crate::png::Png;
+
+const ARCHIVE_ENTRY_NAME: &str = "data.bin";
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+const LOCAL_HEADER_LENGTH: usize = 30;
+const ZIP_VERSION: u16 = 20;    // version 2.0, what zip tools write for stored entries
+
+// What data after IEND is made to look like
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Camouflage {
+    // Envelope bytes as they are
+    None,
+
+    // Stored entry of a ZIP archive, as in PNG/ZIP polyglots that unzip tools open
+    Zip
+}
+
+// Hide data after IEND, where image viewers stop reading
+pub fn hide(png: &mut Png, data: &[u8], camouflage: Camouflage) -> Result<()> {
+    if !png.trailer().is_empty() {
+        return Err(Error::Usage(format!("PNG already has {} bytes of trailing data after IEND", png.trailer().len())));
+    }
+    if png.chunks().last().is_none_or(|chunk| chunk.chunk_type().to_string() != "IEND") {
+        return Err(Error::InvalidData("PNG must end with an IEND chunk to carry trailing data".to_string()));
+    }
+
+    let trailer = match camouflage {
+        Camouflage::None => data.to_vec(),
+        Camouflage::Zip => zip_archive(ARCHIVE_ENTRY_NAME, data, png.as_bytes().len())?
+    };
+    png.set_trailer(trailer);
+    Ok(())
+}
+
+// Find data hidden by hide after IEND, None if the trailer holds no imgcrypt envelope
+pub fn reveal(png: &Png) -> Option<Vec<u8>> {
+    let trailer = png.trailer();
+    let data = if trailer.starts_with(&LOCAL_HEADER_SIGNATURE.to_le_bytes()) { zip_entry(trailer)? } else { trailer };
+    Some(data.to_vec()).filter(|data| envelope::holds_envelope(data))
+}
+
+// ZIP archive holding data uncompressed in a single entry, with offsets counted from the start of the file it is appended to
+fn zip_archive(name: &str, data: &[u8], start: usize) -> Result<Vec<u8>> {
+    let too_large = || "Message is too large for a ZIP archive";
+    let size = u32::try_from(data.len()).map_err(|_| too_large())?;
+    let start = u32::try_from(start).map_err(|_| too_large())?;
+    let (time, date) = dos_date_time(SystemTime::now());
+    let crc = checksum_ieee(data);
+
+    // fields shared by the local & central headers: version needed, flags, method, time, date, crc, sizes & name length
+    let mut common = Vec::new();
+    for field in [ZIP_VERSION, 0, 0, time, date] {
+        common.extend(field.to_le_bytes());
+    }
+    for field in [crc, size, size] {
+        common.extend(field.to_le_bytes());
+    }
+    common.extend((name.len() as u16).to_le_bytes());
+
+    let mut archive = Vec::new();
+    archive.extend(LOCAL_HEADER_SIGNATURE.to_le_bytes());
+    archive.extend(&common);
+    archive.extend(0u16.to_le_bytes());     // extra field length
+    archive.extend(name.as_bytes());
+    archive.extend(data);
+
+    let central_directory_start = archive.len();
+    archive.extend(CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+    archive.extend(ZIP_VERSION.to_le_bytes());  // version made by
+    archive.extend(&common);
+    for field in [0u16; 4] {                    // extra & comment lengths, disk & internal attributes
+        archive.extend(field.to_le_bytes());
+    }
+    archive.extend(0u32.to_le_bytes());         // external attributes
+    archive.extend(start.to_le_bytes());        // offset of the local header
+    archive.extend(name.as_bytes());
+    let central_directory_size = (archive.len() - central_directory_start) as u32;
+    let central_directory_offset = start.checked_add(central_directory_start as u32).ok_or_else(too_large)?;
+
+    archive.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+    for field in [0u16, 0, 1, 1] {              // disk numbers & entry counts
+        archive.extend(field.to_le_bytes());
+    }
+    archive.extend(central_directory_size.to_le_bytes());
+    archive.extend(central_directory_offset.to_le_bytes());
+    archive.extend(0u16.to_le_bytes());         // comment length
+
+    Ok(archive)
+}
+
+// Data of the first entry of a ZIP archive, if it is stored uncompressed
+fn zip_entry(archive: &[u8]) -> Option<&[u8]> {
+    let read_u16 = |offset: usize| Some(u16::from_le_bytes([*archive.get(offset)?, *archive.get(offset + 1)?]) as usize);
+    let read_u32 = |offset: usize| Some(u32::from_le_bytes(archive.get(offset..offset + 4)?.try_into().ok()?) as usize);
+
+    if read_u16(8)? != 0 {
+        return None;
+    }
+    let start = LOCAL_HEADER_LENGTH + read_u16(26)? + read_u16(28)?;
+    archive.get(start..start + read_u32(18)?)
+}
+
+// Time & date fields of ZIP headers, in 2 second resolution starting from 1980
+fn dos_date_time(time: SystemTime) -> (u16, u16) {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let (days, seconds) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = (year_of_era + era * 400 + (month <= 2) as i64).clamp(1980, 2107);
+
+    let time = (seconds / 3600) << 11 | (seconds % 3600 / 60) << 5 | (seconds % 60 / 2);
+    let date = (year - 1980) << 9 | month << 5 | day;
+    (time as u16, date as u16)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::compression::Algorithm;
+    use crate::envelope::Envelope;
+
+    fn testing_png() -> Png {
+        let chunk = |chunk_type: &str| Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 4]);
+        Png::from_chunks(vec![chunk("IHDR"), chunk("IDAT"), chunk("IEND")])
+    }
+
+    fn testing_data() -> Vec<u8> {
+        Envelope::seal(0, Algorithm::None, b"Secret", b"Secret".to_vec()).as_bytes()
+    }
+
+    #[test]
+    fn test_trailer_roundtrip() {
+        for camouflage in [Camouflage::None, Camouflage::Zip] {
+            let mut png = testing_png();
+            hide(&mut png, &testing_data(), camouflage).unwrap();
+            assert_eq!(reveal(&png).unwrap(), testing_data());
+
+            let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+            assert_eq!(parsed.chunks().len(), 3);
+            assert_eq!(reveal(&parsed).unwrap(), testing_data());
+        }
+    }
+
+    #[test]
+    fn test_zip_archive_layout() {
+        let archive = zip_archive(ARCHIVE_ENTRY_NAME, b"Hello", 100).unwrap();
+        assert!(archive.starts_with(b"PK\x03\x04"));
+        assert_eq!(zip_entry(&archive).unwrap(), b"Hello");
+
+        // end of central directory record points back at the central directory
+        let end = archive.len() - 22;
+        assert_eq!(archive[end..end + 4], END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        let offset = u32::from_le_bytes(archive[end + 16..end + 20].try_into().unwrap()) as usize - 100;
+        assert_eq!(archive[offset..offset + 4], CENTRAL_HEADER_SIGNATURE.to_le_bytes());
+        assert_eq!(archive[offset + 42..offset + 46], 100u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_dos_date_time() {
+        // 2024-02-29 13:45:30 UTC
+        let time = UNIX_EPOCH + std::time::Duration::from_secs(1709214330);
+        assert_eq!(dos_date_time(time), (13 << 11 | 45 << 5 | 15, 44 << 9 | 2 << 5 | 29));
+    }
+
+    #[test]
+    fn test_existing_trailer() {
+        let mut png = testing_png();
+        png.set_trailer(b"Leftover".to_vec());
+        assert!(reveal(&png).is_none());
+        assert!(hide(&mut png, &testing_data(), Camouflage::None).is_err());
+    }
+}